@@ -0,0 +1,225 @@
+use std::collections::HashSet;
+
+use reqwest::blocking::Client;
+
+use crate::{
+  cli::Document,
+  config::Config,
+  converter::{
+    image::{
+      source::ImageSource,
+      store::{ImageStore, StoredImage},
+    },
+    Converter,
+  },
+  error::ApplicationError,
+};
+
+/// Result of verifying a cached image
+#[derive(Debug, PartialEq)]
+pub struct Verification {
+  pub fotolife_url: String,
+  /// Error message if the image does not respond
+  pub error: Option<String>,
+}
+
+/// Check that every Fotolife URL in the store still responds.
+///
+/// # Arguments
+///
+/// * `store` - Image store to verify
+/// * `endpoint` - Origin replacing the one of Fotolife URLs, such as `http://localhost:8080`
+/// * `timeout` - Timeout in seconds for each request
+pub fn verify(store: &ImageStore, endpoint: Option<&str>, timeout: u64) -> Vec<Verification> {
+  let client = Client::builder()
+    .timeout(std::time::Duration::from_secs(timeout))
+    .build()
+    .unwrap();
+
+  store
+    .images
+    .iter()
+    .map(|image| {
+      let url = match endpoint {
+        Some(endpoint) => rewrite_origin(&image.fotolife_url, endpoint),
+        None => image.fotolife_url.clone(),
+      };
+      let error = match client.head(&url).send() {
+        Ok(res) if res.status().is_success() => None,
+        Ok(res) => Some(res.status().to_string()),
+        Err(e) => Some(e.to_string()),
+      };
+
+      Verification {
+        fotolife_url: image.fotolife_url.clone(),
+        error,
+      }
+    })
+    .collect()
+}
+
+/// Replace scheme, host and port of URL with those of endpoint
+fn rewrite_origin(url: &str, endpoint: &str) -> String {
+  match reqwest::Url::parse(url) {
+    Ok(url) => {
+      let query = url.query().map(|q| format!("?{}", q)).unwrap_or_default();
+      format!("{}{}{}", endpoint.trim_end_matches('/'), url.path(), query)
+    }
+    Err(_) => url.into(),
+  }
+}
+
+/// Drop images not referenced by any of documents, and returns dropped ones.
+///
+/// # Arguments
+///
+/// * `store` - Image store to prune
+/// * `documents` - Documents which may reference images, read by `read_document`
+/// * `config` - Converter options to parse documents
+pub fn prune(
+  store: &mut ImageStore,
+  documents: &[Document],
+  config: &Config,
+) -> Result<Vec<StoredImage>, ApplicationError> {
+  let mut keys = HashSet::new();
+  for document in documents {
+    let mut converter = Converter::new(config);
+    converter
+      .parse(&document.markdown)
+      .map_err(|message| ApplicationError::MiscError { message })?;
+
+    for url in &converter.unresolved_images {
+      // Images which cannot be read any more are not referenced
+      if let Some(key) = ImageSource::from(url, &document.base_dir)
+        .ok()
+        .and_then(|source| source.cache_key().ok())
+      {
+        keys.insert(key);
+      }
+    }
+  }
+
+  let (kept, dropped) = std::mem::take(&mut store.images)
+    .into_iter()
+    .partition(|image| keys.contains(&image.original_url));
+  store.images = kept;
+
+  Ok(dropped)
+}
+
+/// Merge images of other stores without duplicates, and returns the number of added ones.
+///
+/// Images having the same key are merged into one, preferring the latest upload.
+pub fn merge(store: &mut ImageStore, others: Vec<ImageStore>) -> usize {
+  let mut added = 0;
+  for image in others.into_iter().flat_map(|other| other.images) {
+    match store.find(&image.original_url) {
+      Some(stored) if stored.uploaded_at >= image.uploaded_at => {
+        let documents = image.documents;
+        let mut image = stored.clone();
+        image.documents = documents;
+        store.insert(image);
+      }
+      Some(_) => store.insert(image),
+      None => {
+        added += 1;
+        store.insert(image);
+      }
+    }
+  }
+
+  added
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::{cli::read_document, hackmd::HackMD};
+  use std::{
+    io::{BufRead, BufReader, Write},
+    net::TcpListener,
+  };
+
+  #[test]
+  fn test_verify_with_stub() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let endpoint = format!("http://{}", listener.local_addr().unwrap());
+    let server = std::thread::spawn(move || {
+      for stream in listener.incoming().take(2) {
+        let mut stream = stream.unwrap();
+        let mut request_line = String::new();
+        BufReader::new(&stream)
+          .read_line(&mut request_line)
+          .unwrap();
+        let status = if request_line.contains("/alive.png") {
+          "200 OK"
+        } else {
+          "404 Not Found"
+        };
+        write!(
+          stream,
+          "HTTP/1.1 {}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+          status
+        )
+        .unwrap();
+      }
+    });
+
+    let mut store = ImageStore::new();
+    store.insert(StoredImage::new("a", "https://cdn.example.com/alive.png"));
+    store.insert(StoredImage::new("b", "https://cdn.example.com/deleted.png"));
+    let verifications = verify(&store, Some(&endpoint), 5);
+    server.join().unwrap();
+
+    assert_eq!(verifications[0].error, None);
+    assert_eq!(verifications[1].error.as_deref(), Some("404 Not Found"));
+  }
+
+  #[test]
+  fn test_prune_and_merge() {
+    let dir = std::env::temp_dir().join("md2hatena-test-cache");
+    std::fs::create_dir_all(&dir).unwrap();
+    let markdown_path = dir.join("note.md");
+    std::fs::write(&markdown_path, "![](https://example.com/used.png)\n").unwrap();
+    // Notes of HackMD are not fetched from local paths
+    let hackmd = HackMD::new("".into());
+    let note = Document {
+      markdown: "![](https://hackmd.io/_uploads/note.png)\n".into(),
+      source: "hackmd:abc".into(),
+      name: "note".into(),
+      title: "note".into(),
+      base_dir: std::path::PathBuf::new(),
+    };
+
+    let mut store = ImageStore::new();
+    store.insert(StoredImage::new("https://example.com/used.png", "f1"));
+    store.insert(StoredImage::new("https://example.com/unused.png", "f2"));
+    store.insert(StoredImage::new(
+      "https://hackmd.io/_uploads/note.png",
+      "f4",
+    ));
+    let documents = [
+      read_document(&markdown_path.to_string_lossy(), &hackmd).unwrap(),
+      note,
+    ];
+    let dropped = prune(&mut store, &documents, &Config::new()).unwrap();
+    assert_eq!(dropped.len(), 1);
+    assert_eq!(dropped[0].original_url, "https://example.com/unused.png");
+
+    let mut teammate = ImageStore::new();
+    teammate.insert(StoredImage {
+      documents: vec!["other.md".into()],
+      ..StoredImage::new("https://example.com/used.png", "f1")
+    });
+    teammate.insert(StoredImage::new("https://example.com/new.png", "f3"));
+    assert_eq!(merge(&mut store, vec![teammate.clone(), teammate]), 1);
+    assert_eq!(store.images.len(), 3);
+    assert_eq!(
+      store
+        .find("https://example.com/used.png")
+        .unwrap()
+        .documents,
+      vec!["other.md"]
+    );
+  }
+}
@@ -1,22 +1,46 @@
-use std::{env, fs, io::Write, path, process::exit};
+use std::{collections::HashSet, env, fs, io::Write, path, process::exit};
 
 use crate::{
-  converter::image::ResolvedImage, error::ApplicationError, hackmd::HackMD, hatena::HatenaUploader,
+  cache,
+  config::Config,
+  converter::{
+    front_matter::FrontMatter,
+    image::{
+      process::ImageProcessor,
+      source::{content_hash, download_name, stored_extension, ImageSource},
+      store::{ImageStore, StoredImage},
+      ResolvedImage,
+    },
+  },
+  download::Downloader,
+  error::ApplicationError,
+  hackmd::{note::NoteRef, HackMD},
+  hatena::{
+    blog::{Entry, HatenaBlog, PublishedEntry},
+    error::UploadError,
+    store::EntryStore,
+    HatenaUploader, UploadFailure,
+  },
+  store::VersionedStore,
   util,
 };
 
-use clap::Parser;
+use chrono::Utc;
+use clap::{Parser, Subcommand};
 use colored::*;
-use hatena_rs::oauth::HatenaConsumerInfo;
-use indicatif::ProgressBar;
+use indicatif::{MultiProgress, ProgressBar};
 
 /// Command line arguments
 #[derive(Parser, Debug)]
 #[clap(author, version, about, long_about = None)]
+#[clap(subcommand_negates_reqs = true)]
 pub struct Args {
-  /// Path to Markdown file to convert
-  #[clap(value_parser)]
-  pub markdown_path: String,
+  #[clap(subcommand)]
+  pub command: Option<Command>,
+
+  /// Path to Markdown file to convert, or URL of HackMD note such as `https://hackmd.io/@team/xyz` or `hackmd:NOTEID`
+  #[clap(value_parser, required = true)]
+  pub markdown_path: Option<String>,
 
   /// Directory to save temporary images
   #[clap(short('d'), long("download-dir"), value_parser)]
@@ -31,23 +55,206 @@ pub struct Args {
   pub no_resolve: bool,
 
   /// Path to cache file which stores mapping of image URL and Hatena Fotolife ID
-  #[clap(short('i'), long("image-cache"), value_parser)]
+  #[clap(short('i'), long("image-cache"), value_parser, global = true)]
   pub image_mapping: Option<String>,
 
   /// Path to output HTML file
   #[clap(short('o'), long("output"), value_parser)]
   pub output: Option<String>,
 
+  /// Minimum heading level, which precedes config file and front matter
+  #[clap(long("heading-min"), value_parser)]
+  pub heading_min: Option<usize>,
+
+  /// Codeblock type, which precedes config file and front matter
+  #[clap(long("codeblock-type"), value_parser)]
+  pub codeblock_type: Option<String>,
+
+  /// Auto-indexing for headings, which precedes config file and front matter
+  #[clap(long("indexing"), value_parser)]
+  pub indexing: Option<bool>,
+
+  /// Post the converted HTML to Hatena Blog as a draft or a public entry
+  #[clap(long("publish"), value_parser = ["draft", "public"])]
+  pub publish: Option<String>,
+
+  /// Path to cache file which stores mapping of markdown and Hatena Blog entry
+  #[clap(long("entry-cache"), value_parser)]
+  pub entry_mapping: Option<String>,
+
+  /// Overwrite the published entry even if it is edited on Hatena Blog
+  #[clap(long("force"), value_parser, default_value = "false")]
+  pub force: bool,
+
   /// Path to configuration file
   #[clap(
     short('c'),
     long("config"),
     value_parser,
+    global = true,
     default_value = "~/.md2hatena.config.yml"
   )]
   pub config_path: String,
 }
 
+/// Subcommands
+#[derive(Subcommand, Debug)]
+pub enum Command {
+  /// Manage the image mapping cache
+  Cache {
+    #[clap(subcommand)]
+    command: CacheCommand,
+  },
+}
+
+/// Subcommands to manage the image mapping cache
+#[derive(Subcommand, Debug)]
+pub enum CacheCommand {
+  /// List cached images
+  List,
+  /// Check that every cached Fotolife URL still responds
+  Verify {
+    /// Origin to send requests instead of Fotolife, such as `http://localhost:8080`
+    #[clap(long("endpoint"), value_parser)]
+    endpoint: Option<String>,
+  },
+  /// Drop cached images not referenced by any of given markdown files or HackMD notes
+  Prune {
+    /// Paths to markdown files, or URLs of HackMD notes
+    #[clap(value_parser, required = true)]
+    markdown_paths: Vec<String>,
+    /// Only show images to be dropped
+    #[clap(long("dry-run"), value_parser, default_value = "false")]
+    dry_run: bool,
+  },
+  /// Export the cache into a file
+  Export {
+    #[clap(value_parser)]
+    path: String,
+  },
+  /// Replace the cache with a file exported before
+  Import {
+    #[clap(value_parser)]
+    path: String,
+  },
+  /// Merge caches of teammates into the cache without duplicates
+  Merge {
+    #[clap(value_parser, required = true)]
+    paths: Vec<String>,
+  },
+}
+
+/// Run subcommand to manage the image mapping cache
+///
+/// # Arguments
+///
+/// * `command` - Subcommand to run
+/// * `config` - Config which specifies the path of the cache
+pub fn run_cache_command(command: &CacheCommand, config: &Config) -> Result<(), ApplicationError> {
+  if config.image_mapping.is_empty() {
+    return Err(ApplicationError::MiscError {
+      message: "image mapping cache is not configured".into(),
+    });
+  }
+  let mut store = ImageStore::open(&config.image_mapping)?;
+
+  match command {
+    CacheCommand::List => {
+      for image in &store.images {
+        let uploaded_at = image
+          .uploaded_at
+          .map(|date| date.to_rfc3339())
+          .unwrap_or_else(|| "-".into());
+        println!(
+          "{} -> {} ({}, {} documents)",
+          image.original_url,
+          image.fotolife_url,
+          uploaded_at,
+          image.documents.len()
+        );
+      }
+      println!(
+        "{} {} images cached",
+        "[+]".green().bold(),
+        store.images.len()
+      );
+    }
+    CacheCommand::Verify { endpoint } => {
+      let verifications = cache::verify(&store, endpoint.as_deref(), config.timeout);
+      let broken = verifications
+        .iter()
+        .filter(|verification| verification.error.is_some())
+        .collect::<Vec<_>>();
+      for verification in &broken {
+        eprintln!(
+          "{} {}: {}",
+          "[!] Broken:".red().bold(),
+          verification.fotolife_url,
+          verification.error.as_ref().unwrap()
+        );
+      }
+      println!(
+        "{} {} of {} images respond",
+        "[+]".green().bold(),
+        verifications.len() - broken.len(),
+        verifications.len()
+      );
+    }
+    CacheCommand::Prune {
+      markdown_paths,
+      dry_run,
+    } => {
+      // API token of HackMD is required only if notes are given
+      let hackmd_apitoken = if markdown_paths
+        .iter()
+        .any(|input| NoteRef::parse(input).is_some())
+      {
+        get_hackmd_api_token()
+      } else {
+        String::new()
+      };
+      let mut hackmd = HackMD::new(hackmd_apitoken);
+      hackmd.set_timeout(config.download_timeout);
+      let documents = markdown_paths
+        .iter()
+        .map(|input| read_document(input, &hackmd))
+        .collect::<Result<Vec<_>, _>>()?;
+      let dropped = cache::prune(&mut store, &documents, config)?;
+      for image in &dropped {
+        println!("{} {}", "[-]".yellow().bold(), image.original_url);
+      }
+      if !dry_run {
+        store.save(&config.image_mapping)?;
+      }
+      println!("{} {} images pruned", "[+]".green().bold(), dropped.len());
+    }
+    CacheCommand::Export { path } => {
+      store.save(path)?;
+      println!("{} Exported to {}", "[+]".green().bold(), path);
+    }
+    CacheCommand::Import { path } => {
+      let imported = ImageStore::open(path)?;
+      imported.save(&config.image_mapping)?;
+      println!(
+        "{} {} images imported",
+        "[+]".green().bold(),
+        imported.images.len()
+      );
+    }
+    CacheCommand::Merge { paths } => {
+      let others = paths
+        .iter()
+        .map(|path| ImageStore::open(path))
+        .collect::<Result<Vec<_>, _>>()?;
+      let added = cache::merge(&mut store, others);
+      store.save(&config.image_mapping)?;
+      println!("{} {} images added", "[+]".green().bold(), added);
+    }
+  }
+
+  Ok(())
+}
+
 /// Exit with error message
 pub fn panic_with_error(err: ApplicationError) {
   match err {
@@ -66,6 +273,15 @@ pub fn panic_with_error(err: ApplicationError) {
     ApplicationError::ConfigParseFailure(e) => {
       eprintln!("{} {}", "[!] Error:".red().bold(), e);
     }
+    ApplicationError::ImageProcessFailure(e) => {
+      eprintln!("{} {}", "[!] Error:".red().bold(), e);
+    }
+    ApplicationError::ImageStoreFailure(e) => {
+      eprintln!("{} {}", "[!] Error:".red().bold(), e);
+    }
+    ApplicationError::PublishFailure(e) => {
+      eprintln!("{} {}", "[!] Error:".red().bold(), e);
+    }
     ApplicationError::MiscError { message } => {
       eprintln!("{} {}", "[!] Error:".red().bold(), message);
     }
@@ -73,10 +289,17 @@ pub fn panic_with_error(err: ApplicationError) {
   exit(1);
 }
 
-/// Check necessary API tokens of Hatena in envvar, and returns them
+/// Print warnings found while conversion
+pub fn print_warnings(warnings: &[String]) {
+  for warning in warnings {
+    eprintln!("{} {}", "[!] Warning:".yellow().bold(), warning);
+  }
+}
+
+/// Check necessary API tokens of Hatena in envvar, and returns consumer key and secret
 ///
 /// Note that this function panics if necessary tokens not found.
-pub fn get_hatena_api_token() -> HatenaConsumerInfo {
+pub fn get_hatena_api_token() -> (String, String) {
   if env::var("HATENA_CONSUMER_KEY").is_err() {
     println!(
       "{} {} is not set as envvar.",
@@ -94,11 +317,10 @@ pub fn get_hatena_api_token() -> HatenaConsumerInfo {
     exit(1);
   }
 
-  HatenaConsumerInfo::new(
-    &env::var("HATENA_CONSUMER_KEY").unwrap(),
-    &env::var("HATENA_CONSUMER_SECRET").unwrap(),
+  (
+    env::var("HATENA_CONSUMER_KEY").unwrap(),
+    env::var("HATENA_CONSUMER_SECRET").unwrap(),
   )
-  .unwrap()
 }
 
 /// Check necessary API tokens of HackMD in envvar, and returns HackMD API token
@@ -133,22 +355,82 @@ pub fn read_markdown_file(path: &str) -> String {
   }
 }
 
-/// Download images from Network with progress bar
+/// Markdown to convert, read from a local file or HackMD
+pub struct Document {
+  pub markdown: String,
+  /// Key to identify images and entry of the document, such as absolute path or `hackmd:ID`
+  pub source: String,
+  /// Name without extension to derive output path, such as `notes/hello`
+  pub name: String,
+  /// Title of the entry used if front matter has no title
+  pub title: String,
+  /// Directory to resolve relative paths of local images
+  pub base_dir: path::PathBuf,
+}
+
+/// Read markdown from a local file, or fetch it from HackMD if `input` refers to a note
+///
+/// # Arguments
+///
+/// * `input` - Path to markdown file, URL of HackMD note or `hackmd:ID`
+/// * `hackmd` - HackMD client to fetch notes
+pub fn read_document(input: &str, hackmd: &HackMD) -> Result<Document, ApplicationError> {
+  if let Some(note_ref) = NoteRef::parse(input) {
+    println!("{} Fetching note from HackMD", "[+]".green().bold());
+    let note = hackmd.get_note(&note_ref)?;
+    return Ok(Document {
+      source: note.source(),
+      name: note.file_stem(),
+      title: if note.title.is_empty() {
+        note.id.clone()
+      } else {
+        note.title.clone()
+      },
+      markdown: note.content,
+      base_dir: path::PathBuf::new(),
+    });
+  }
+
+  let markdown_path = path::Path::new(input);
+  Ok(Document {
+    markdown: read_markdown_file(input),
+    source: fs::canonicalize(markdown_path)?
+      .to_string_lossy()
+      .to_string(),
+    name: markdown_path
+      .with_extension("")
+      .to_string_lossy()
+      .to_string(),
+    title: markdown_path
+      .file_stem()
+      .map(|stem| stem.to_string_lossy().to_string())
+      .unwrap_or_default(),
+    base_dir: markdown_path
+      .parent()
+      .map(|dir| dir.to_path_buf())
+      .unwrap_or_default(),
+  })
+}
+
+/// Download images from Network with progress bars, and returns URLs of images failed to be downloaded
+///
+/// Failures are reported as warnings instead of aborting.
 pub fn download_images(
   images: &[String],
   download_dir: &path::Path,
   hackmd_client: &HackMD,
   use_cache: bool,
-) {
+  downloader: &Downloader,
+) -> Vec<String> {
   if images.is_empty() {
-    return;
+    return vec![];
   }
 
   let images: Vec<String> = if use_cache {
     images
       .iter()
       .filter(|image| {
-        let image_path = download_dir.join(image.split('/').last().unwrap());
+        let image_path = download_dir.join(download_name(image));
         !image_path.exists()
       })
       .map(|image| image.to_string())
@@ -158,52 +440,66 @@ pub fn download_images(
   };
 
   println!("{} Downloading images from HackMD", "[+]".green().bold(),);
-  let pb = ProgressBar::new(images.len() as u64);
-  pb.set_style(
-    indicatif::ProgressStyle::with_template(
-      "  {spinner:.green} [{pos}/{len}] [{elapsed_precise}] [{bar:40.cyan/blue}] {msg}",
-    )
-    .unwrap()
-    .progress_chars("#>-"),
+  // Images used more than once are downloaded only once, not by workers racing on a file
+  let mut seen = HashSet::new();
+  let images = images
+    .into_iter()
+    .filter(|image| seen.insert(image.clone()))
+    .map(|image| {
+      let save_path = download_dir.join(download_name(&image));
+      (image, save_path)
+    })
+    .collect();
+  let failures = downloader.download(
+    images,
+    |url| hackmd_client.get_photo(url),
+    &MultiProgress::new(),
   );
 
-  for image in images {
-    pb.set_message(image.clone());
-    std::thread::sleep(std::time::Duration::from_millis(500));
-    let save_path = download_dir.join(image.split('/').last().unwrap());
-    let bytes = hackmd_client.get_photo(&image).unwrap();
-    fs::write(save_path, bytes).unwrap();
-    pb.inc(1);
-  }
+  let warnings = failures
+    .iter()
+    .map(|failure| format!("failed to download {}: {:?}", failure.url, failure.error))
+    .collect::<Vec<_>>();
+  print_warnings(&warnings);
 
-  pb.finish_with_message("Done");
+  failures.into_iter().map(|failure| failure.url).collect()
 }
 
-/// Upload images to Hatena Fotolife
+/// Upload images to Hatena Fotolife, then returns uploaded images and images failed to be uploaded.
+///
+/// Remote images must be downloaded into `download_dir` beforehand.
+/// Images are processed by `processor`, then the processed copies are uploaded.
+/// Images whose content is identical to already uploaded ones reuse their Fotolife URL.
+/// Image store is saved after every upload, so that a rerun resumes from failed images.
+///
+/// # Arguments
+///
+/// * `images` - URLs of images written in markdown, and their sources
+#[allow(clippy::too_many_arguments)]
 pub fn upload_images(
-  #[allow(clippy::ptr_arg)] images: &Vec<String>,
+  images: &[(String, ImageSource)],
   download_dir: &path::Path,
+  processor: &ImageProcessor,
   hatena: &mut HatenaUploader,
   use_cache: bool,
   cache_path: &str,
-) -> Result<Vec<String>, ApplicationError> {
+  store: &mut ImageStore,
+  document: &str,
+) -> Result<(Vec<ResolvedImage>, Vec<UploadFailure>), ApplicationError> {
   if images.is_empty() {
-    return Ok(vec![]);
+    return Ok((vec![], vec![]));
   }
-  let mut fotolife_ids = vec![];
-  hatena.init_profile().unwrap();
+  let mut resolved_images = vec![];
+  let mut failures = vec![];
+  let mut avoided_uploads = 0;
 
-  let images = if use_cache {
+  let images: Vec<&(String, ImageSource)> = if use_cache {
     images
       .iter()
-      .filter(|image| {
-        let image_path = download_dir.join(image.split('/').last().unwrap());
-        !image_path.exists()
-      })
-      .map(|image| image.to_string())
+      .filter(|(_, image)| !image.path(download_dir).exists())
       .collect()
   } else {
-    images.clone()
+    images.iter().collect()
   };
 
   println!(
@@ -219,28 +515,91 @@ pub fn upload_images(
     .progress_chars("#>-"),
   );
 
-  for image in images {
-    let save_path = download_dir.join(image.split('/').last().unwrap());
-    let extension = save_path.extension().unwrap().to_str().unwrap();
-    pb.set_message(save_path.to_string_lossy().to_string());
-    // Download image
-    let uuid = util::gen_uuid();
-    let uploaded_path = hatena.upload(&save_path, &uuid).unwrap();
-    fotolife_ids.push(hatena.fotolife_url(&uploaded_path, extension));
-    // Cache image mapping
-    if !cache_path.is_empty() {
-      let resolved_image = ResolvedImage::from(
-        vec![image.to_string()],
-        vec![hatena.fotolife_url(&uploaded_path, extension)],
-      );
-      ResolvedImage::cache_to(&resolved_image, cache_path)?;
+  for (url, image) in images {
+    pb.set_message(url.clone());
+    match upload_image(image, download_dir, processor, hatena, store, document) {
+      Ok((stored_image, avoided)) => {
+        if avoided {
+          avoided_uploads += 1;
+        }
+        resolved_images.push(ResolvedImage {
+          original_url: url.clone(),
+          fotolife_url: stored_image.fotolife_url.clone(),
+        });
+        store.insert(stored_image);
+        // Cache image mapping
+        if !cache_path.is_empty() {
+          store.save(cache_path)?;
+        }
+      }
+      Err(error) => failures.push(UploadFailure {
+        url: url.clone(),
+        error,
+      }),
     }
 
     pb.inc(1);
   }
 
   pb.finish_with_message("Done");
-  Ok(fotolife_ids)
+  if avoided_uploads > 0 {
+    println!(
+      "{} {} uploads avoided by identical images on Fotolife",
+      "[+]".green().bold(),
+      avoided_uploads
+    );
+  }
+  let warnings = failures
+    .iter()
+    .map(|failure| format!("failed to upload {}: {}", failure.url, failure.error))
+    .collect::<Vec<_>>();
+  print_warnings(&warnings);
+
+  Ok((resolved_images, failures))
+}
+
+/// Upload an image unless identical one is already uploaded, and returns its entry of image store.
+///
+/// Returned flag is `true` if the upload is avoided.
+fn upload_image(
+  image: &ImageSource,
+  download_dir: &path::Path,
+  processor: &ImageProcessor,
+  hatena: &mut HatenaUploader,
+  store: &ImageStore,
+  document: &str,
+) -> Result<(StoredImage, bool), UploadError> {
+  let original_path = image.path(download_dir);
+  let hash = content_hash(&original_path)?;
+  if let Some(uploaded_image) = store.find_by_hash(&hash) {
+    let stored_image = StoredImage {
+      original_url: image.cache_key()?,
+      documents: vec![document.into()],
+      ..uploaded_image.clone()
+    };
+    return Ok((stored_image, true));
+  }
+
+  let save_path = processor.process(&original_path, download_dir)?;
+  // Checked before uploading, so that no image is left on Fotolife without its URL
+  let extension = stored_extension(&save_path)?.ok_or_else(|| UploadError::UnknownFormat {
+    path: save_path.display().to_string(),
+  })?;
+  let uuid = util::gen_uuid();
+  let image_id = hatena.upload(&save_path, &uuid)?;
+  let stored_image = StoredImage {
+    content_hash: Some(hash),
+    fotolife_id: Some(image_id.clone()),
+    uploaded_at: Some(Utc::now()),
+    size: Some(fs::metadata(&save_path)?.len()),
+    documents: vec![document.into()],
+    ..StoredImage::new(
+      &image.cache_key()?,
+      &hatena.fotolife_url(&image_id, &extension)?,
+    )
+  };
+
+  Ok((stored_image, false))
 }
 
 pub fn write_result_html(html: &str, output_path: &str) {
@@ -259,3 +618,87 @@ pub fn write_result_html(html: &str, output_path: &str) {
     output_path.display()
   );
 }
+
+/// Post converted HTML to Hatena Blog, and returns the created or updated entry
+///
+/// Title and categories are taken from front matter, and the title defaults to the one of the document.
+/// If the document was published before, its entry is updated instead of creating new one.
+/// The update is refused when the entry was edited on Hatena Blog after the last publish, unless `force`.
+///
+/// # Arguments
+///
+/// * `html` - Converted HTML
+/// * `front_matter` - Front matter of the note
+/// * `document` - Document converted into `html`
+/// * `config` - Config having the blog to publish
+/// * `hatena` - Uploader whose OAuth session is shared
+/// * `draft` - Whether the entry is posted as a draft
+/// * `force` - Overwrite the entry even if it was edited on Hatena Blog
+pub fn publish_entry(
+  html: &str,
+  front_matter: Option<&FrontMatter>,
+  document: &Document,
+  config: &Config,
+  hatena: &mut HatenaUploader,
+  draft: bool,
+  force: bool,
+) -> Result<PublishedEntry, ApplicationError> {
+  if config.blog_id.is_empty() && config.blog_endpoint.is_empty() {
+    return Err(ApplicationError::MiscError {
+      message: "`blog_id` is not set in config".into(),
+    });
+  }
+  let credentials = hatena.credentials()?;
+  let author = hatena.myname()?;
+  let endpoint = if config.blog_endpoint.is_empty() {
+    HatenaBlog::default_endpoint(&author, &config.blog_id)
+  } else {
+    config.blog_endpoint.clone()
+  };
+  let blog = HatenaBlog::new(&endpoint, credentials, config.timeout);
+
+  let title = front_matter
+    .and_then(|front_matter| front_matter.title.clone())
+    .unwrap_or_else(|| document.title.clone());
+  let entry = Entry {
+    title,
+    author,
+    content: html.into(),
+    categories: front_matter
+      .map(|front_matter| front_matter.tags.clone())
+      .unwrap_or_default(),
+    draft,
+  };
+
+  let do_entry_cache = !config.entry_mapping.is_empty();
+  let mut store = if do_entry_cache {
+    EntryStore::open(&config.entry_mapping)?
+  } else {
+    print_warnings(&[
+      "`entry_mapping` is empty, so a new entry is created on every publish".into(),
+    ]);
+    EntryStore::new()
+  };
+  let source = document.source.clone();
+
+  println!(
+    "{} Publishing entry to Hatena Blog as {}",
+    "[+]".green().bold(),
+    if draft { "a draft" } else { "a public entry" }
+  );
+  let mut warnings = vec![];
+  let published = blog.publish(&entry, &source, &mut store, force, &mut warnings);
+  print_warnings(&warnings);
+  let published = published?;
+
+  if do_entry_cache {
+    store.save(&config.entry_mapping)?;
+  }
+  println!(
+    "{} Published entry: {}",
+    "[+]".green().bold(),
+    published.url.as_deref().unwrap_or(&published.edit_url)
+  );
+
+  Ok(published)
+}
@@ -1,7 +1,11 @@
-use crate::{converter::options::HeadingDepth, error::ApplicationError};
+use crate::{
+  converter::{front_matter::FrontMatter, image::process::ImageProcessor, options::HeadingDepth},
+  error::ApplicationError,
+};
 
 use serde::{Deserialize, Serialize};
 use shellexpand::tilde;
+use std::collections::HashMap;
 
 /// Convert options for Markdown to Hatena HTML
 #[derive(Debug, PartialEq, Serialize, Deserialize, Clone)]
@@ -23,23 +27,125 @@ pub struct Config {
   #[serde(default = "default_timeout")]
   pub timeout: u64,
 
+  /// Number of retries for uploads failed by transient errors
+  #[serde(default = "default_upload_retries")]
+  pub upload_retries: u32,
+
+  /// Domain of Hatena Blog to publish entries, such as `example.hatenablog.com`
+  #[serde(default)]
+  pub blog_id: String,
+
+  /// Root endpoint of Hatena Blog AtomPub API, derived from `blog_id` if empty
+  #[serde(default)]
+  pub blog_endpoint: String,
+
+  /// Path to cache file which stores mapping of markdown and Hatena Blog entry.
+  /// If empty, every publish creates a new entry.
+  #[serde(default = "default_entry_mapping")]
+  pub entry_mapping: String,
+
+  /// Number of images downloaded concurrently
+  #[serde(default = "default_download_concurrency")]
+  pub download_concurrency: usize,
+
+  /// Timeout in seconds for downloading each image
+  #[serde(default = "default_download_timeout")]
+  pub download_timeout: u64,
+
+  /// Number of retries for downloads failed by transient errors
+  #[serde(default = "default_download_retries")]
+  pub download_retries: u32,
+
   /// Output HTML file path
   #[serde(default = "default_output")]
   pub output: String,
 
+  /// Maximum width of uploaded images, wider ones are downscaled
+  #[serde(default)]
+  pub image_max_width: Option<u32>,
+
+  /// Format of uploaded PNG images
+  /// `original` to keep PNG, `jpeg` or `webp` to convert into JPEG or lossy WebP at `image_quality`
+  #[serde(default = "default_image_format")]
+  pub image_format: String,
+
+  /// Quality of JPEG and WebP images re-encoded before uploading, from 1 to 100. 85 if not given.
+  #[serde(default)]
+  pub image_quality: Option<u8>,
+
+  /// Strip metadata such as EXIF from uploaded images
+  #[serde(default = "default_image_strip_metadata")]
+  pub image_strip_metadata: bool,
+
+  /// Representation of images uploaded to Fotolife
+  /// `html` for `<figure>` and `<img>`, `fotolife` for Hatena's `[f:id:...]` notation
+  #[serde(default = "default_image_style")]
+  pub image_style: String,
+
   /// Codeblock type
   #[serde(default = "default_codeblock_type")]
   pub codeblock_type: String,
 
+  /// Math type
+  /// `tex` for Hatena's `[tex:...]` notation, `mathml` for static MathML, `none` to leave math as it is
+  #[serde(default = "default_math_mode")]
+  pub math_mode: String,
+
   /// Auto-indexing for headings
   #[serde(default = "default_indexing")]
   pub indexing: bool,
+
+  /// Deepest heading level to be indexed
+  /// eg: If 2, `#` is indexed as `1.` and `##` as `1.1`
+  #[serde(default = "default_indexing_depth")]
+  pub indexing_depth: usize,
+
+  /// Format of top-level indexes: `arabic` (`1.`), `roman` (`I.`) or `japanese` (`第1章`)
+  #[serde(default = "default_indexing_format")]
+  pub indexing_format: String,
+
+  /// Style of table of contents generated from `[TOC]`
+  /// `hatena` for Hatena's `[:contents]` notation, `html` for nested list of links
+  #[serde(default = "default_toc_style")]
+  pub toc_style: String,
+
+  /// Footnote type
+  /// `hatena` for Hatena's `((...))` notation, `html` for footnote section at the end
+  #[serde(default = "default_footnote_type")]
+  pub footnote_type: String,
+
+  /// Style of links standing alone in paragraphs
+  /// `embed` for Hatena's blog card, `title` for link with page title, `link` to leave them as they are
+  #[serde(default = "default_link_card_style")]
+  pub link_card_style: String,
+
+  /// Styles of standalone links for each host, which precede `link_card_style`
+  /// eg: `twitter.com: link`
+  #[serde(default)]
+  pub link_card_rules: HashMap<String, String>,
+
+  /// Class names of HackMD containers (`:::info` etc)
+  /// eg: `info: "alert alert-info"`
+  #[serde(default)]
+  pub container_classes: HashMap<String, String>,
 }
 
 fn default_download_dir() -> String {
   tilde(&"./.md2hatena-imgs").into()
 }
 
+fn default_download_concurrency() -> usize {
+  4
+}
+
+fn default_download_timeout() -> u64 {
+  30
+}
+
+fn default_download_retries() -> u32 {
+  3
+}
+
 fn default_output() -> String {
   tilde(&"").into()
 }
@@ -48,28 +154,92 @@ fn default_timeout() -> u64 {
   10
 }
 
+fn default_upload_retries() -> u32 {
+  3
+}
+
 fn default_image_mapping() -> String {
   "".into()
 }
 
+fn default_entry_mapping() -> String {
+  tilde(&"~/.md2hatena.entries.json").into()
+}
+
+fn default_image_format() -> String {
+  "original".into()
+}
+
+fn default_image_strip_metadata() -> bool {
+  false
+}
+
+fn default_image_style() -> String {
+  "html".into()
+}
+
 fn default_codeblock_type() -> String {
   "pure".into()
 }
 
+fn default_math_mode() -> String {
+  "tex".into()
+}
+
 fn default_indexing() -> bool {
   false
 }
 
+fn default_toc_style() -> String {
+  "hatena".into()
+}
+
+fn default_footnote_type() -> String {
+  "hatena".into()
+}
+
+fn default_link_card_style() -> String {
+  "embed".into()
+}
+
+fn default_indexing_depth() -> usize {
+  1
+}
+
+fn default_indexing_format() -> String {
+  "arabic".into()
+}
+
 impl Default for Config {
   fn default() -> Self {
     Config {
       heading_min: HeadingDepth::default(),
       download_dir: default_download_dir(),
       timeout: default_timeout(),
+      upload_retries: default_upload_retries(),
       image_mapping: default_image_mapping(),
+      blog_id: "".into(),
+      blog_endpoint: "".into(),
+      entry_mapping: default_entry_mapping(),
+      download_concurrency: default_download_concurrency(),
+      download_timeout: default_download_timeout(),
+      download_retries: default_download_retries(),
       output: default_output(),
+      image_max_width: None,
+      image_format: default_image_format(),
+      image_quality: None,
+      image_strip_metadata: default_image_strip_metadata(),
+      image_style: default_image_style(),
       codeblock_type: default_codeblock_type(),
+      math_mode: default_math_mode(),
       indexing: default_indexing(),
+      indexing_depth: default_indexing_depth(),
+      indexing_format: default_indexing_format(),
+      toc_style: default_toc_style(),
+      footnote_type: default_footnote_type(),
+      link_card_style: default_link_card_style(),
+      link_card_rules: HashMap::new(),
+      container_classes: HashMap::new(),
     }
   }
 }
@@ -79,12 +249,18 @@ impl Config {
     Config::default()
   }
 
-  /// Migrate command-line arguments to Config from config file
+  /// Migrate command-line arguments and front matter to Config from config file
+  ///
+  /// Front matter precedes config file, and command-line arguments precede front matter.
   ///
   /// # Arguments
   ///
   /// * `arg` - command-line arguments
-  pub fn from_args(args: &crate::cli::Args) -> Result<Self, ApplicationError> {
+  /// * `front_matter` - front matter of the note
+  pub fn from_args(
+    args: &crate::cli::Args,
+    front_matter: Option<&FrontMatter>,
+  ) -> Result<Self, ApplicationError> {
     let config_path_str = &tilde(&args.config_path).to_string();
     let config_path = std::path::Path::new(config_path_str);
     let mut config: Config = if config_path.exists() {
@@ -94,6 +270,11 @@ impl Config {
       Config::default()
     };
 
+    if let Some(front_matter) = front_matter {
+      config.merge_front_matter(front_matter);
+    }
+    config.merge_converter_args(args);
+
     if args.download_dir.is_some() {
       config.download_dir = args.download_dir.clone().unwrap();
     }
@@ -108,20 +289,90 @@ impl Config {
     }
     config.image_mapping = tilde(&config.image_mapping).into();
 
+    if args.entry_mapping.is_some() {
+      config.entry_mapping = args.entry_mapping.clone().unwrap();
+    }
+    config.entry_mapping = tilde(&config.entry_mapping).into();
+
+    let tmp = std::path::Path::new(args.markdown_path.as_deref().unwrap_or_default());
+    config.set_output(args, &tmp.with_extension("").to_string_lossy());
+
+    // Invalid image options are reported before downloading any image
+    ImageProcessor::new(&config)?;
+
+    Ok(config)
+  }
+
+  /// Apply front matter of the note to Config built by `from_args` without front matter.
+  ///
+  /// Command-line arguments still precede front matter.
+  ///
+  /// # Arguments
+  ///
+  /// * `args` - Command-line arguments which built the config
+  /// * `front_matter` - Front matter of the note
+  pub fn apply_front_matter(&mut self, args: &crate::cli::Args, front_matter: &FrontMatter) {
+    self.merge_front_matter(front_matter);
+    self.merge_converter_args(args);
+  }
+
+  /// Override converter options by command-line arguments
+  fn merge_converter_args(&mut self, args: &crate::cli::Args) {
+    if let Some(heading_min) = args.heading_min {
+      self.heading_min.set(heading_min);
+    }
+
+    if let Some(codeblock_type) = &args.codeblock_type {
+      self.codeblock_type = codeblock_type.clone();
+    }
+
+    if let Some(indexing) = args.indexing {
+      self.indexing = indexing;
+    }
+  }
+
+  /// Set output path given by `args`, or derive it from the name of the document
+  ///
+  /// # Arguments
+  ///
+  /// * `args` - Command line arguments
+  /// * `name` - Name of the document without extension, such as `notes/hello`
+  pub fn set_output(&mut self, args: &crate::cli::Args, name: &str) {
     if args.output.is_some() {
-      config.output = args.output.clone().unwrap();
+      self.output = args.output.clone().unwrap();
     } else {
-      let tmp = std::path::Path::new(&args.markdown_path);
-      config.output = tilde(&format!(
-        "{}.{}",
-        tmp.with_extension("").to_string_lossy().to_string(),
-        "html"
-      ))
-      .into();
+      self.output = format!("{}.{}", name, "html");
     }
-    config.output = tilde(&config.output).into();
+    self.output = tilde(&self.output).into();
+  }
 
-    Ok(config)
+  /// Override converter options by front matter of the note
+  pub fn merge_front_matter(&mut self, front_matter: &FrontMatter) {
+    let front_matter = front_matter.clone();
+    if let Some(heading_min) = front_matter.heading_min {
+      self.heading_min = heading_min;
+    }
+    if let Some(codeblock_type) = front_matter.codeblock_type {
+      self.codeblock_type = codeblock_type;
+    }
+    if let Some(math_mode) = front_matter.math_mode {
+      self.math_mode = math_mode;
+    }
+    if let Some(indexing) = front_matter.indexing {
+      self.indexing = indexing;
+    }
+    if let Some(indexing_depth) = front_matter.indexing_depth {
+      self.indexing_depth = indexing_depth;
+    }
+    if let Some(indexing_format) = front_matter.indexing_format {
+      self.indexing_format = indexing_format;
+    }
+    if let Some(toc_style) = front_matter.toc_style {
+      self.toc_style = toc_style;
+    }
+    if let Some(footnote_type) = front_matter.footnote_type {
+      self.footnote_type = footnote_type;
+    }
   }
 }
 
@@ -145,12 +396,72 @@ mod tests {
       Config {
         heading_min: HeadingDepth::new(3),
         timeout: 30,
+        upload_retries: default_upload_retries(),
         download_dir: "~/.md2hatena-cache".into(),
         image_mapping: default_image_mapping(),
+        blog_id: "".into(),
+        blog_endpoint: "".into(),
+        entry_mapping: default_entry_mapping(),
+        download_concurrency: default_download_concurrency(),
+        download_timeout: default_download_timeout(),
+        download_retries: default_download_retries(),
         output: "~/test.html".into(),
+        image_max_width: None,
+        image_format: default_image_format(),
+        image_quality: None,
+        image_strip_metadata: default_image_strip_metadata(),
+        image_style: default_image_style(),
         codeblock_type: default_codeblock_type(),
+        math_mode: default_math_mode(),
         indexing: default_indexing(),
+        indexing_depth: default_indexing_depth(),
+        indexing_format: default_indexing_format(),
+        toc_style: default_toc_style(),
+        footnote_type: default_footnote_type(),
+        link_card_style: default_link_card_style(),
+        link_card_rules: HashMap::new(),
+        container_classes: HashMap::new(),
       }
     );
   }
+
+  #[test]
+  fn test_config_precedence() {
+    use clap::Parser;
+
+    let args = crate::cli::Args::parse_from([
+      "md2hatena",
+      "note.md",
+      "--config",
+      "/nonexistent/config.yml",
+      "--heading-min",
+      "4",
+    ]);
+    let front_matter = FrontMatter {
+      heading_min: Some(HeadingDepth::new(2)),
+      codeblock_type: Some("highlightjs".into()),
+      ..Default::default()
+    };
+    let config = Config::from_args(&args, Some(&front_matter)).unwrap();
+
+    assert_eq!(config.heading_min, HeadingDepth::new(4));
+    assert_eq!(config.codeblock_type, "highlightjs");
+    assert_eq!(config.indexing, default_indexing());
+
+    // Front matter read after loading config has the same precedence
+    let mut applied = Config::from_args(&args, None).unwrap();
+    applied.apply_front_matter(&args, &front_matter);
+    assert_eq!(applied, config);
+  }
+
+  #[test]
+  fn test_invalid_image_options() {
+    use clap::Parser;
+
+    let path = std::env::temp_dir().join("md2hatena-test-invalid-config.yml");
+    std::fs::write(&path, "image_format: webp\nimage_quality: 101\n").unwrap();
+    let args =
+      crate::cli::Args::parse_from(["md2hatena", "note.md", "--config", path.to_str().unwrap()]);
+    assert!(Config::from_args(&args, None).is_err());
+  }
 }
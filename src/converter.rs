@@ -1,13 +1,36 @@
+pub mod anchor;
 pub mod codeblock;
+pub mod container;
+pub mod embed;
+pub mod fence;
+pub mod footnote;
+pub mod front_matter;
 pub mod image;
+pub mod link_card;
+pub mod math;
+pub mod numbering;
 pub mod options;
-
-use crate::config::Config;
-use image::ResolvedImage;
-
-use pulldown_cmark::{html, CodeBlockKind, Event, HeadingLevel, LinkType, Options, Parser, Tag};
-
-use self::codeblock::Codeblock;
+pub mod placeholder;
+pub mod toc;
+
+use crate::{config::Config, util::escape_html};
+use image::{
+  fotolife::FotolifeImage, layout::ImageLayout, size::ImageSize, ImageStyle, ResolvedImage,
+};
+
+use pulldown_cmark::{html, CodeBlockKind, Event, HeadingLevel, Options, Parser, Tag};
+
+use self::{
+  anchor::Anchors,
+  codeblock::Codeblock,
+  footnote::FootnoteStyle,
+  front_matter::FrontMatter,
+  link_card::LinkCardStyle,
+  math::Math,
+  numbering::{HeadingNumbering, NumberFormat},
+  placeholder::Placeholders,
+  toc::{TocEntry, TocStyle},
+};
 
 #[derive(Debug)]
 struct ImageAltMapping {
@@ -15,14 +38,30 @@ struct ImageAltMapping {
   url: String,
 }
 
+#[derive(Debug)]
+struct Heading {
+  level: HeadingLevel,
+  text: String,
+  id: String,
+}
+
 /// Converter of HackMD note to Hatena HTML
 pub struct Converter {
   config: Config,
   pub unresolved_images: Vec<String>,
+  /// Warnings found while parsing and converting
+  pub warnings: Vec<String>,
+  /// Front matter of the note
+  pub front_matter: Option<FrontMatter>,
   resolved_images: Vec<ResolvedImage>,
+  /// Images failed to be uploaded, which are replaced with placeholders
+  failed_images: Vec<String>,
   markdown: String,
   image_alt_mappings: Vec<ImageAltMapping>,
+  headings: Vec<Heading>,
   codeblock: Box<dyn Codeblock>,
+  math: Option<Box<dyn Math>>,
+  placeholders: Placeholders,
 }
 
 impl Converter {
@@ -34,14 +73,25 @@ impl Converter {
   pub fn new(config: &Config) -> Self {
     let codeblock = <dyn Codeblock>::from(&config.codeblock_type)
       .unwrap_or(<dyn Codeblock>::from("pure").unwrap());
+    let math = if config.math_mode == "none" {
+      None
+    } else {
+      Some(<dyn Math>::from(&config.math_mode).unwrap_or(<dyn Math>::from("tex").unwrap()))
+    };
 
     Self {
       config: config.clone(),
       unresolved_images: vec![],
+      warnings: vec![],
+      front_matter: None,
       resolved_images: vec![],
+      failed_images: vec![],
       markdown: "".into(),
       image_alt_mappings: vec![],
+      headings: vec![],
       codeblock,
+      math,
+      placeholders: Placeholders::new(),
     }
   }
 
@@ -51,11 +101,49 @@ impl Converter {
   ///
   /// * `markdown` - HackMD note
   pub fn parse(&mut self, markdown: &str) -> Result<(), String> {
-    self.markdown = markdown.into();
+    let mut warnings = vec![];
+    let (front_matter, body) = FrontMatter::parse(markdown, &mut warnings);
+    self.parse_body(front_matter, &body)?;
+    self.warnings.splice(0..0, warnings);
+
+    Ok(())
+  }
+
+  /// Parse HackMD note whose front matter is already parsed by `FrontMatter::parse`
+  ///
+  /// # Arguments
+  ///
+  /// * `front_matter` - Front matter of the note
+  /// * `markdown` - HackMD note without front matter
+  pub fn parse_body(
+    &mut self,
+    front_matter: Option<FrontMatter>,
+    markdown: &str,
+  ) -> Result<(), String> {
+    self.placeholders.clear();
+    self.warnings.clear();
+    self.front_matter = front_matter;
+    // Embeds and math are extracted first so that warnings name lines of the original note
+    let markdown = embed::expand_embeds(markdown, &mut self.placeholders, &mut self.warnings);
+    let markdown = match &self.math {
+      Some(math) => math::extract_math(
+        &markdown,
+        math.as_ref(),
+        &mut self.placeholders,
+        &mut self.warnings,
+      ),
+      None => markdown,
+    };
+    let markdown = container::expand_containers(&markdown, &self.config.container_classes);
+    let markdown = toc::mark_toc(&markdown);
+    let markdown = image::size::wrap_sized_images(&markdown);
+    self.markdown = markdown.clone();
     self.resolved_images.clear();
+    self.failed_images.clear();
     self.unresolved_images.clear();
+    self.headings.clear();
 
-    self.pre_parse(markdown);
+    self.pre_parse(&markdown);
 
     Ok(())
   }
@@ -72,11 +160,55 @@ impl Converter {
   ///
   /// - Check URL of unresolved images, then push to `Self.unresolved_images`
   /// - Check alt text of images, then push to `Self.image_alt_mappings`
+  /// - Check text of headings and generate their IDs, then push to `Self.headings`
+  /// - Check in-document links, then warn if they point to no headings
   fn pre_parse(&mut self, markdown: &str) {
     let mut image_url: Option<String> = None;
+    let mut heading: Option<Heading> = None;
+    let mut anchors = Anchors::new();
+    let mut fragments: Vec<String> = vec![];
 
     let parser = Parser::new_ext(markdown, Options::all()).map(|event| match &event {
+      Event::Start(Tag::Heading(level, fragment, _)) => {
+        heading = Some(Heading {
+          level: *level,
+          text: "".into(),
+          id: fragment.unwrap_or_default().into(),
+        });
+        event
+      }
+
+      Event::End(Tag::Heading(..)) => {
+        if let Some(mut heading) = heading.take() {
+          let id = if heading.id.is_empty() {
+            anchor::slugify(&heading.text)
+          } else {
+            heading.id.clone()
+          };
+          heading.id = anchors.unique(&id);
+          self.headings.push(heading);
+        }
+        event
+      }
+
+      Event::Start(Tag::Link(_, dest, _)) => {
+        if let Some(fragment) = dest.strip_prefix('#') {
+          fragments.push(fragment.to_string());
+        }
+        event
+      }
+
+      Event::Code(text) => {
+        if let Some(heading) = heading.as_mut() {
+          heading.text.push_str(text);
+        }
+        event
+      }
+
       Event::Text(text) => {
+        if let Some(heading) = heading.as_mut() {
+          heading.text.push_str(text);
+        }
         if image_url.is_some() {
           self.image_alt_mappings.push(ImageAltMapping {
             alt: text.to_string(),
@@ -87,15 +219,13 @@ impl Converter {
         event
       }
 
-      Event::Start(Tag::Image(LinkType::Inline, url, _)) => {
+      Event::Start(Tag::Image(_, dest, _)) => {
+        let (url, _) = ImageSize::split_url(dest);
         let resolved_image = self
           .resolved_images
           .iter()
-          .find(|image| image.original_url == url.to_string());
-        let unresolved_image = self
-          .unresolved_images
-          .iter()
-          .find(|&image| image == &url.to_string());
+          .find(|image| image.original_url == url);
+        let unresolved_image = self.unresolved_images.iter().find(|&image| image == url);
         if unresolved_image.is_none() && resolved_image.is_none() {
           self.unresolved_images.push(url.to_string());
         }
@@ -104,7 +234,7 @@ impl Converter {
         event
       }
 
-      Event::End(Tag::Image(LinkType::Inline, ..)) => {
+      Event::End(Tag::Image(..)) => {
         image_url = None;
         event
       }
@@ -114,14 +244,41 @@ impl Converter {
 
     let mut new_html = String::with_capacity(markdown.len() * 2);
     html::push_html(&mut new_html, parser);
+
+    let ids = self.heading_ids();
+    let unresolved = fragments
+      .into_iter()
+      .filter(|fragment| anchor::resolve_fragment(fragment, &ids).is_none())
+      .map(|fragment| format!("link to `#{}` points to no heading", fragment))
+      .collect::<Vec<_>>();
+    self.warnings.extend(unresolved);
   }
 
   fn convert_internal<'a>(&mut self, markdown: &str) -> Result<String, String> {
     let mut in_image = false;
-    let mut index = 1;
+    let mut heading_index = 0;
+    let mut numbering = if self.config.indexing {
+      let format = NumberFormat::from(&self.config.indexing_format).unwrap_or(NumberFormat::Arabic);
+      Some(HeadingNumbering::new(self.config.indexing_depth, format))
+    } else {
+      None
+    };
+    let toc_entries = self
+      .headings
+      .iter()
+      .map(|heading| TocEntry {
+        level: self.adjust_heading_level(heading.level) as usize,
+        text: &heading.text,
+        id: &heading.id,
+      })
+      .collect::<Vec<_>>();
+    let toc_style = TocStyle::from(&self.config.toc_style).unwrap_or(TocStyle::Hatena);
+    let toc = toc::render_toc(toc_style, &toc_entries);
+    let ids = self.heading_ids();
+    let image_style = ImageStyle::from(&self.config.image_style).unwrap_or(ImageStyle::Html);
 
     let parser = Parser::new_ext(&markdown, Options::all()).map(|event| match &event {
-      Event::End(Tag::Image(LinkType::Inline, _, _)) => {
+      Event::End(Tag::Image(..)) => {
         if in_image {
           in_image = false;
           vec![]
@@ -133,12 +290,18 @@ impl Converter {
       Event::End(Tag::Heading(level, fragment, classes)) => {
         vec![
             Event::End(Tag::Heading(
-              self.config.heading_min.add(*level as usize - 1).to_level(),
+              self.adjust_heading_level(*level),
               *fragment,
               classes.clone(),
             )),
         ]
       }
+      // Put back HTML fragments of preprocessors
+      Event::Html(html) if toc::is_toc_marker(html) => vec![Event::Html(toc.as_str().into())],
+      Event::Html(html) => match self.placeholders.resolve(html) {
+        Some(fragment) => vec![Event::Html(fragment.into())],
+        None => vec![event],
+      },
       Event::Text(_) => {
         if in_image {
           vec![]
@@ -149,54 +312,112 @@ impl Converter {
       // - Replace image URL
       // - Add <figcaption> tag if image has alt text
       Event::Start(tag) => match &tag {
-        Tag::Image(LinkType::Inline, url, title) => {
+        Tag::Image(link_type, dest, title) => {
+          let (url, size) = ImageSize::split_url(dest);
           let resolved_image = self
             .resolved_images
             .iter()
-            .find(|image| image.original_url == url.to_string());
+            .find(|image| image.original_url == url);
           match resolved_image {
             Some(resolved_image) => {
               in_image = true;
 
+              let layout = ImageLayout::parse(title, size);
               let alt_text = self
                 .image_alt_mappings
                 .iter()
-                .find(|mapping| mapping.url == url.to_string())
+                .find(|mapping| mapping.url == url)
                 .map(|mapping| mapping.alt.clone())
-                .unwrap_or_else(|| title.to_string());
-              vec!(
-                Event::Html(format!(
-                  r#"<figure class="figure-image figure-image-fotolife mceNonEditable" title="{}">"#, alt_text).into()
-                ),
+                .or_else(|| layout.caption.clone())
+                .unwrap_or_default();
+              let caption = layout.caption.clone().unwrap_or_else(|| alt_text.clone());
+              let figure_start = Event::Html(format!(
+                r#"<figure class="figure-image figure-image-fotolife mceNonEditable" title="{}"{}>"#,
+                escape_html(&caption), layout.to_style()
+              ).into());
+              let figcaption = vec!(
+                Event::Html(r#"<figcaption class="mceEditable">"#.into()),
+                Event::Text(caption.clone().into()),
+                Event::Html(r#"</figcaption>"#.into()),
+              );
+
+              let fotolife_image = match image_style {
+                ImageStyle::Fotolife => FotolifeImage::from_url(&resolved_image.fotolife_url),
+                ImageStyle::Html => None,
+              };
+              if let Some(fotolife_image) = fotolife_image {
+                let notation = Event::Html(format!(
+                  "[{}:plain{}]",
+                  fotolife_image.to_notation(),
+                  layout.to_fotolife_options(&caption)
+                ).into());
+                // Figure is needed only for caption and alignment, as Hatena's editor does
+                if layout.caption.is_none() && layout.align.is_none() {
+                  return vec!(notation);
+                }
+                let mut events = vec!(figure_start, notation);
+                if layout.caption.is_some() {
+                  events.extend(figcaption);
+                }
+                events.push(Event::Html(r#"</figure>"#.into()));
+                return events;
+              }
+
+              let mut events = vec!(
+                figure_start,
                 Event::Html(format!(
-                  r#"<img src="{}" alt="{}" class="hatena-fotolife" loading="lazy" itemprop="image" title="">"#,
-                  resolved_image.fotolife_url, alt_text
+                  r#"<img src="{}" alt="{}" class="hatena-fotolife" loading="lazy" itemprop="image" title=""{}>"#,
+                  escape_html(&resolved_image.fotolife_url), escape_html(&alt_text), layout.size.to_attributes()
                 ).into()),
                 Event::Html(r#"</img>"#.into()),
-                Event::Html(r#"<figcaption class="mceEditable">"#.into()),
-                Event::Text(alt_text.into()),
-                Event::Html(r#"</figcaption>"#.into()),
-                Event::Html(r#"</figure>"#.into()),
+              );
+              events.extend(figcaption);
+              events.push(Event::Html(r#"</figure>"#.into()));
+              events
+            }
+            // Placeholder is left so that the image can be found after a rerun
+            None if self.failed_images.iter().any(|image| image == url) => {
+              in_image = true;
+
+              let alt_text = self
+                .image_alt_mappings
+                .iter()
+                .find(|mapping| mapping.url == url)
+                .map(|mapping| mapping.alt.clone())
+                .or_else(|| ImageLayout::parse(title, None).caption)
+                .unwrap_or_default();
+              vec!(
+                Event::Html(format!("<!-- md2hatena: failed to upload {} -->", escape_html(url)).into()),
+                Event::Html(r#"<span class="md2hatena-failed-image">"#.into()),
+                Event::Text(format!("[image: {}]", alt_text).into()),
+                Event::Html(r#"</span>"#.into()),
               )
             }
-            None => vec!(event),
+            // Size suffix is dropped so that the URL is not broken
+            None => vec!(Event::Start(Tag::Image(*link_type, url.to_string().into(), title.clone()))),
           }
         }
 
         // Adjust heading level based on options
-        Tag::Heading(level, fragment, classes) => {
-          let cur_index = index;
+        Tag::Heading(level, _, classes) => {
+          let heading = match self.headings.get(heading_index) {
+            Some(heading) => heading,
+            None => return vec!(event),
+          };
+          heading_index += 1;
 
           let mut events = vec![
             Event::Start(Tag::Heading(
-              self.config.heading_min.add(*level as usize - 1).to_level(),
-              *fragment,
+              self.adjust_heading_level(*level),
+              Some(heading.id.as_str()),
               classes.clone(),
             )),
           ];
-          if level == &HeadingLevel::H1 {
-            index += 1;
-            events.push(Event::Text(format!("{}. ", cur_index).into()));
+          if let Some(number) = numbering
+            .as_mut()
+            .and_then(|numbering| numbering.next(*level as usize, &heading.text))
+          {
+            events.push(Event::Text(number.into()));
           }
 
           events
@@ -205,16 +426,46 @@ impl Converter {
 
         Tag::CodeBlock(CodeBlockKind::Fenced(code_name)) => self.codeblock.codeblock_start(code_name),
 
+        // Rewrite in-document links to match heading IDs
+        Tag::Link(link_type, dest, title) if dest.starts_with('#') => {
+          match anchor::resolve_fragment(&dest[1..], &ids) {
+            Some(id) => vec!(Event::Start(Tag::Link(*link_type, format!("#{}", id).into(), title.clone()))),
+            None => vec!(event),
+          }
+        }
+
         _ => vec!(event),
       },
       _ => vec!(event),
     }).flatten();
 
+    let events = parser.collect::<Vec<_>>();
+    let footnote_style =
+      FootnoteStyle::from(&self.config.footnote_type).unwrap_or(FootnoteStyle::Hatena);
+    let (events, undefined_footnotes) = match footnote_style {
+      FootnoteStyle::Hatena => footnote::inline_footnotes(events),
+      FootnoteStyle::Html => (events, vec![]),
+    };
+    let link_card_style =
+      LinkCardStyle::from(&self.config.link_card_style).unwrap_or(LinkCardStyle::Embed);
+    let events =
+      link_card::convert_link_cards(events, &self.config.link_card_rules, link_card_style);
+
     let mut new_html = String::with_capacity(markdown.len() * 2);
-    html::push_html(&mut new_html, parser);
+    html::push_html(&mut new_html, events.into_iter());
+
+    for label in undefined_footnotes {
+      self
+        .warnings
+        .push(format!("footnote `[^{}]` is not defined", label));
+    }
 
     // Add pre-document of codeblock
     let new_html = format!("{}\n{}", new_html, self.codeblock.postdoc());
+    let new_html = match &self.math {
+      Some(math) => format!("{}{}", new_html, math.postdoc()),
+      None => new_html,
+    };
 
     // Add credit at the start of content
     let new_html = format!("<!-- Converted by md2hatena-rs -->\n{}", new_html);
@@ -222,6 +473,19 @@ impl Converter {
     Ok(new_html)
   }
 
+  fn heading_ids(&self) -> Vec<&str> {
+    self
+      .headings
+      .iter()
+      .map(|heading| heading.id.as_str())
+      .collect()
+  }
+
+  /// Adjust heading level based on `heading_min`
+  fn adjust_heading_level(&self, level: HeadingLevel) -> HeadingLevel {
+    self.config.heading_min.add(level as usize - 1).to_level()
+  }
+
   /// Resolve image URL to Hatena Fotolife URL
   pub fn resolve_images(&mut self, resolved_images: &Vec<ResolvedImage>) {
     for image in resolved_images {
@@ -236,6 +500,19 @@ impl Converter {
       self.resolved_images.push(image.clone());
     }
   }
+
+  /// Mark images failed to be uploaded, which are rendered as placeholders
+  ///
+  /// # Arguments
+  ///
+  /// * `urls` - URLs of images written in markdown
+  pub fn fail_images(&mut self, urls: &[String]) {
+    for url in urls {
+      if !self.failed_images.contains(url) {
+        self.failed_images.push(url.clone());
+      }
+    }
+  }
 }
 
 #[cfg(test)]
@@ -252,4 +529,120 @@ mod tests {
 
     println!("{:?}", converter.unresolved_images);
   }
+
+  #[test]
+  fn test_resolve_images_of_all_link_types() {
+    let markdown = "\
+      ![inline](https://example.com/inline.png)\n\n\
+      ![reference][ref]\n\n\
+      ![collapsed][]\n\n\
+      ![shortcut]\n\n\
+      [ref]: https://example.com/reference.png\n\
+      [collapsed]: https://example.com/collapsed.png\n\
+      [shortcut]: https://example.com/shortcut.png\n";
+    let mut converter = Converter::new(&Config::new());
+    converter.parse(markdown).unwrap();
+
+    let kinds = ["inline", "reference", "collapsed", "shortcut"];
+    let urls = kinds
+      .iter()
+      .map(|kind| format!("https://example.com/{}.png", kind))
+      .collect::<Vec<_>>();
+    assert_eq!(converter.unresolved_images, urls);
+
+    let fotolife_urls = kinds
+      .iter()
+      .map(|kind| format!("https://cdn-ak.f.st-hatena.com/{}.png", kind))
+      .collect::<Vec<_>>();
+    converter.resolve_images(&ResolvedImage::from(urls, fotolife_urls.clone()));
+    assert!(converter.unresolved_images.is_empty());
+
+    let html = converter.convert().unwrap();
+    for (kind, fotolife_url) in kinds.iter().zip(fotolife_urls) {
+      assert!(html.contains(&format!(r#"<img src="{}" alt="{}""#, fotolife_url, kind)));
+      assert!(html.contains(&format!(
+        "<figcaption class=\"mceEditable\">{}</figcaption>",
+        kind
+      )));
+    }
+  }
+
+  #[test]
+  fn test_placeholder_of_failed_images() {
+    let markdown =
+      "![uploaded](https://example.com/a.png)\n\n![failed](https://example.com/b.png)\n";
+    let mut converter = Converter::new(&Config::new());
+    converter.parse(markdown).unwrap();
+    converter.resolve_images(&ResolvedImage::from(
+      vec!["https://example.com/a.png".into()],
+      vec!["https://cdn-ak.f.st-hatena.com/a.png".into()],
+    ));
+    converter.fail_images(&["https://example.com/b.png".into()]);
+    assert_eq!(
+      converter.unresolved_images,
+      vec!["https://example.com/b.png"]
+    );
+
+    let html = converter.convert().unwrap();
+    assert!(html.contains(r#"<img src="https://cdn-ak.f.st-hatena.com/a.png""#));
+    assert!(html.contains("<!-- md2hatena: failed to upload https://example.com/b.png -->"));
+    assert!(html.contains(r#"<span class="md2hatena-failed-image">[image: failed]</span>"#));
+    assert!(!html.contains(r#"src="https://example.com/b.png""#));
+  }
+
+  #[test]
+  fn test_image_style_and_layout() {
+    let markdown = "\
+      ![a](https://example.com/a.png =300x)\n\n\
+      ![b](https://example.com/b.png)\n\n\
+      ![c](https://example.com/c.png \"A cat {width=400 align=center}\")\n\n\
+      ![d & e](https://example.com/d.png 'Say \"hi\" <b>: [x]')\n";
+    let urls = ["a", "b", "c", "d"]
+      .iter()
+      .map(|name| format!("https://example.com/{}.png", name))
+      .collect::<Vec<_>>();
+    let fotolife_urls = vec![
+      "https://cdn-ak.f.st-hatena.com/images/fotolife/n/nekoneko/20221010/20221010123456.png"
+        .into(),
+      "https://example.com/not-fotolife.png".into(),
+      "https://cdn-ak.f.st-hatena.com/images/fotolife/n/nekoneko/20221010/20221010123457.jpg"
+        .into(),
+      "https://cdn-ak.f.st-hatena.com/images/fotolife/n/nekoneko/20221010/20221010123458.png"
+        .into(),
+    ];
+
+    let mut config = Config::new();
+    config.image_style = "fotolife".into();
+    let mut converter = Converter::new(&config);
+    converter.parse(markdown).unwrap();
+    converter.resolve_images(&ResolvedImage::from(urls.clone(), fotolife_urls.clone()));
+    let html = converter.convert().unwrap();
+    assert!(html.contains("<p>[f:id:nekoneko:20221010123456p:plain:w300:title=a]</p>"));
+    // Images not on Fotolife fall back to HTML
+    assert!(html.contains(r#"<img src="https://example.com/not-fotolife.png" alt="b""#));
+    assert!(html.contains(
+      "<figure class=\"figure-image figure-image-fotolife mceNonEditable\" title=\"A cat\" style=\"text-align: center\">\
+      [f:id:nekoneko:20221010123457j:plain:w400:title=A cat]\
+      <figcaption class=\"mceEditable\">A cat</figcaption></figure>"
+    ));
+    assert!(html.contains(
+      "title=\"Say &quot;hi&quot; &lt;b&gt;: [x]\">\
+      [f:id:nekoneko:20221010123458p:plain:title=Say &quot;hi&quot; &lt;b&gt; x]"
+    ));
+
+    let mut converter = Converter::new(&Config::new());
+    converter.parse(markdown).unwrap();
+    converter.resolve_images(&ResolvedImage::from(urls, fotolife_urls));
+    let html = converter.convert().unwrap();
+    assert!(html.contains(
+      r#"alt="a" class="hatena-fotolife" loading="lazy" itemprop="image" title="" width="300">"#
+    ));
+    assert!(html.contains(r#"title="A cat" style="text-align: center">"#));
+    assert!(html.contains(
+      r#"alt="c" class="hatena-fotolife" loading="lazy" itemprop="image" title="" width="400">"#
+    ));
+    assert!(html.contains("<figcaption class=\"mceEditable\">A cat</figcaption>"));
+    assert!(html.contains(r#"alt="d &amp; e" class="hatena-fotolife""#));
+    assert!(html.contains(r#"title="Say &quot;hi&quot; &lt;b&gt;: [x]"><img"#));
+  }
 }
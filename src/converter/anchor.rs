@@ -0,0 +1,97 @@
+use std::collections::HashMap;
+
+use percent_encoding::percent_decode_str;
+
+/// Generate slug of heading text following HackMD's rules.
+///
+/// Whitespaces are replaced with `-`, and ASCII punctuations are removed.
+/// Other characters, including Japanese ones, are kept as they are.
+pub fn slugify(text: &str) -> String {
+  text
+    .split_whitespace()
+    .collect::<Vec<_>>()
+    .join("-")
+    .chars()
+    .filter(|c| !(c.is_ascii_punctuation() && *c != '-' && *c != '_'))
+    .collect()
+}
+
+/// Generator of unique heading IDs
+#[derive(Debug, Default)]
+pub struct Anchors {
+  /// Number of headings having the same slug
+  counts: HashMap<String, usize>,
+}
+
+impl Anchors {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  /// Returns unique ID for a heading.
+  ///
+  /// As HackMD does, duplicated IDs are suffixed by their index among duplicates,
+  /// such as `Setup`, `Setup1` and `Setup2`.
+  ///
+  /// # Arguments
+  ///
+  /// * `id` - Slug of heading, or explicit ID given by `{#id}`
+  pub fn unique(&mut self, id: &str) -> String {
+    let count = self.counts.entry(id.to_string()).or_insert(0);
+    *count += 1;
+    if *count == 1 {
+      id.to_string()
+    } else {
+      format!("{}{}", id, *count - 1)
+    }
+  }
+}
+
+/// Find heading ID which in-document link `#fragment` points to.
+///
+/// The fragment is percent-decoded, then compared with IDs as it is,
+/// as a slug, and case-insensitively in this order.
+///
+/// # Arguments
+///
+/// * `fragment` - Fragment of link without `#`
+/// * `ids` - IDs of headings in the document
+pub fn resolve_fragment<'a>(fragment: &str, ids: &[&'a str]) -> Option<&'a str> {
+  let fragment = percent_decode_str(fragment).decode_utf8_lossy();
+  let slug = slugify(&fragment);
+
+  ids
+    .iter()
+    .find(|&&id| id == fragment)
+    .or_else(|| ids.iter().find(|&&id| id == slug))
+    .or_else(|| {
+      ids
+        .iter()
+        .find(|&&id| id.to_lowercase() == slug.to_lowercase())
+    })
+    .copied()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_slugify_and_resolve() {
+    let mut anchors = Anchors::new();
+    let ids = ["Setup (Linux)", "環境 構築!", "Setup (Linux)"]
+      .iter()
+      .map(|text| anchors.unique(&slugify(text)))
+      .collect::<Vec<_>>();
+    assert_eq!(ids, vec!["Setup-Linux", "環境-構築", "Setup-Linux1"]);
+
+    let ids = ids.iter().map(|id| id.as_str()).collect::<Vec<_>>();
+    assert_eq!(
+      resolve_fragment("%E7%92%B0%E5%A2%83-%E6%A7%8B%E7%AF%89", &ids),
+      Some("環境-構築")
+    );
+    assert_eq!(resolve_fragment("setup-linux", &ids), Some("Setup-Linux"));
+    assert_eq!(resolve_fragment("Setup-Linux1", &ids), Some("Setup-Linux1"));
+    assert_eq!(resolve_fragment("missing", &ids), None);
+  }
+}
@@ -0,0 +1,107 @@
+use std::collections::HashMap;
+
+use super::fence::FenceTracker;
+
+/// Kinds of HackMD containers and their default classes
+const DEFAULT_CONTAINERS: [(&str, &str); 4] = [
+  ("info", "alert alert-info"),
+  ("success", "alert alert-success"),
+  ("warning", "alert alert-warning"),
+  ("danger", "alert alert-danger"),
+];
+
+/// Expand HackMD's container blocks (`:::info` ... `:::`) into `<div>` blocks.
+///
+/// Contents of containers are left as markdown, so that they are converted as usual.
+/// Containers of unknown kinds are left as they are.
+///
+/// # Arguments
+///
+/// * `markdown` - HackMD note
+/// * `classes` - Mapping of container kind and class names, which precedes default ones
+pub fn expand_containers(markdown: &str, classes: &HashMap<String, String>) -> String {
+  let mut fence = FenceTracker::new();
+  // Length of colons and whether the container is expanded
+  let mut stack: Vec<(usize, bool)> = vec![];
+  let mut lines = vec![];
+
+  for line in markdown.lines() {
+    if fence.feed(line) {
+      lines.push(line.to_string());
+      continue;
+    }
+
+    let trimmed = line.trim();
+    let colons = trimmed.chars().take_while(|&c| c == ':').count();
+    if colons < 3 {
+      lines.push(line.to_string());
+      continue;
+    }
+
+    let kind = trimmed[colons..].trim();
+    if kind.is_empty() {
+      // Closing marker must be at least as long as the opening one
+      match stack.last() {
+        Some(&(len, expanded)) if colons >= len => {
+          stack.pop();
+          if expanded {
+            lines.push("\n</div>\n".into());
+          } else {
+            lines.push(line.to_string());
+          }
+        }
+        _ => lines.push(line.to_string()),
+      }
+    } else {
+      let kind = kind.split_whitespace().next().unwrap();
+      match class_of(kind, classes) {
+        Some(class) => {
+          stack.push((colons, true));
+          lines.push(format!("<div class=\"{}\">\n", class));
+        }
+        None => {
+          stack.push((colons, false));
+          lines.push(line.to_string());
+        }
+      }
+    }
+  }
+
+  // Close unterminated containers
+  while let Some((_, expanded)) = stack.pop() {
+    if expanded {
+      lines.push("\n</div>\n".into());
+    }
+  }
+
+  let mut expanded = lines.join("\n");
+  expanded.push('\n');
+  expanded
+}
+
+fn class_of(kind: &str, classes: &HashMap<String, String>) -> Option<String> {
+  if let Some(class) = classes.get(kind) {
+    return Some(class.clone());
+  }
+  DEFAULT_CONTAINERS
+    .iter()
+    .find(|(name, _)| name == &kind)
+    .map(|(_, class)| class.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_expand_nested_containers() {
+    let markdown = "::::warning\nouter\n:::info\ninner\n:::\n::::\n```\n:::info\n```";
+    let mut classes = HashMap::new();
+    classes.insert("warning".to_string(), "box-warn".to_string());
+
+    assert_eq!(
+      expand_containers(markdown, &classes),
+      "<div class=\"box-warn\">\n\nouter\n<div class=\"alert alert-info\">\n\ninner\n\n</div>\n\n\n</div>\n\n```\n:::info\n```\n"
+    );
+  }
+}
@@ -0,0 +1,125 @@
+use super::{
+  fence::{code_span_len, FenceTracker},
+  placeholder::Placeholders,
+};
+use crate::util::escape_html;
+
+/// Expand HackMD's embed macros (`{%youtube id %}` etc) into placeholders.
+///
+/// Services which Hatena can embed by URL are converted into `[url:embed]` notation,
+/// and the others into `<iframe>`.
+/// Fenced codeblocks and code spans are left untouched.
+/// Macros of unknown services are left as text, with a warning naming its line.
+///
+/// # Arguments
+///
+/// * `markdown` - HackMD note
+/// * `placeholders` - Store to put expanded embeds
+/// * `warnings` - Warnings of macros which cannot be expanded
+pub fn expand_embeds(
+  markdown: &str,
+  placeholders: &mut Placeholders,
+  warnings: &mut Vec<String>,
+) -> String {
+  let mut fence = FenceTracker::new();
+  let mut lines = vec![];
+
+  for (lineno, line) in markdown.lines().enumerate() {
+    if fence.feed(line) {
+      lines.push(line.to_string());
+      continue;
+    }
+
+    let mut replaced = String::with_capacity(line.len());
+    let mut rest = line;
+    while let Some(pos) = rest.find(['`', '{']) {
+      replaced.push_str(&rest[..pos]);
+      rest = &rest[pos..];
+
+      if rest.starts_with('`') {
+        let len = code_span_len(rest);
+        replaced.push_str(&rest[..len]);
+        rest = &rest[len..];
+        continue;
+      }
+
+      let end = match rest.strip_prefix("{%").and_then(|inner| inner.find("%}")) {
+        Some(end) => end + 2,
+        None => {
+          replaced.push('{');
+          rest = &rest[1..];
+          continue;
+        }
+      };
+
+      let mut args = rest[2..end].split_whitespace();
+      let service = args.next().unwrap_or_default();
+      let id = args.next().unwrap_or_default();
+      match embed(service, id) {
+        Ok(html) => replaced.push_str(&placeholders.inline(html)),
+        Err(message) => {
+          warnings.push(format!("line {}: {}", lineno + 1, message));
+          replaced.push_str(&rest[..end + 2]);
+        }
+      }
+      rest = &rest[end + 2..];
+    }
+    replaced.push_str(rest);
+
+    lines.push(replaced);
+  }
+
+  let mut expanded = lines.join("\n");
+  expanded.push('\n');
+  expanded
+}
+
+/// Returns HTML embedding the content of a service
+fn embed(service: &str, id: &str) -> Result<String, String> {
+  if service.is_empty() {
+    return Err("embed macro `{%%}` has no service".into());
+  }
+  if id.is_empty() {
+    return Err(format!("embed macro `{{%{} %}}` has no ID", service));
+  }
+
+  let id = escape_html(id);
+  match service {
+    "youtube" => Ok(format!("[https://www.youtube.com/watch?v={}:embed]", id)),
+    "gist" => Ok(format!("[https://gist.github.com/{}:embed]", id)),
+    "speakerdeck" => Ok(format!("[https://speakerdeck.com/{}:embed]", id)),
+    "slideshare" => Ok(format!("[https://www.slideshare.net/{}:embed]", id)),
+    "vimeo" => Ok(format!(
+      r#"<iframe src="https://player.vimeo.com/video/{}" width="640" height="360" frameborder="0" allow="autoplay; fullscreen; picture-in-picture" allowfullscreen></iframe>"#,
+      id
+    )),
+    _ => Err(format!("unknown embed service `{}`", service)),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_expand_embeds() {
+    let markdown = "{%youtube dQw4w9WgXcQ %}\n`{%gist a/b %}` {%vimeo 1 %}\n{%niconico sm9 %}";
+    let mut placeholders = Placeholders::new();
+    let mut warnings = vec![];
+    let expanded = expand_embeds(markdown, &mut placeholders, &mut warnings);
+
+    assert_eq!(
+      expanded,
+      format!(
+        "{}\n`{{%gist a/b %}}` {}\n{{%niconico sm9 %}}\n",
+        r#"<md2hatena-placeholder data-id="0"></md2hatena-placeholder>"#,
+        r#"<md2hatena-placeholder data-id="1"></md2hatena-placeholder>"#
+      )
+    );
+    assert_eq!(
+      placeholders.resolve(r#"<md2hatena-placeholder data-id="0">"#),
+      Some("[https://www.youtube.com/watch?v=dQw4w9WgXcQ:embed]")
+    );
+    assert_eq!(warnings, vec!["line 3: unknown embed service `niconico`"]);
+  }
+}
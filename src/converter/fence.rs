@@ -0,0 +1,98 @@
+/// Tracker of fenced codeblocks used by line-based preprocessing of markdown.
+///
+/// Preprocessors must not rewrite the content of codeblocks,
+/// so they feed every line to this tracker and skip the ones inside fences.
+#[derive(Debug, Default)]
+pub struct FenceTracker {
+  /// Character and length of the currently open fence
+  fence: Option<(char, usize)>,
+}
+
+impl FenceTracker {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  /// Feed a line and returns `true` if the line is a part of fenced codeblock,
+  /// including opening and closing fences.
+  ///
+  /// # Arguments
+  ///
+  /// * `line` - Line of markdown without trailing newline
+  pub fn feed(&mut self, line: &str) -> bool {
+    let trimmed = line.trim_start_matches(' ');
+    let marker = if line.len() - trimmed.len() <= 3 {
+      fence_marker(trimmed)
+    } else {
+      None
+    };
+
+    match (self.fence, marker) {
+      (None, Some(marker)) => {
+        self.fence = Some(marker);
+        true
+      }
+      (None, None) => false,
+      (Some((c, len)), Some((mc, mlen))) => {
+        // Closing fence must not have info string
+        if c == mc && mlen >= len && trimmed[mlen..].trim().is_empty() {
+          self.fence = None;
+        }
+        true
+      }
+      (Some(_), None) => true,
+    }
+  }
+
+  /// Whether the tracker is currently inside fenced codeblock
+  pub fn in_fence(&self) -> bool {
+    self.fence.is_some()
+  }
+}
+
+fn fence_marker(line: &str) -> Option<(char, usize)> {
+  let c = line.chars().next()?;
+  if c != '`' && c != '~' {
+    return None;
+  }
+  let len = line.chars().take_while(|&x| x == c).count();
+  if len < 3 {
+    return None;
+  }
+  // Backtick fences cannot have backticks in its info string
+  if c == '`' && line[len..].contains('`') {
+    return None;
+  }
+
+  Some((c, len))
+}
+
+/// Returns length of code span at the start of `s`, including backticks.
+///
+/// If the code span is not closed, only the length of opening backticks is returned.
+///
+/// # Arguments
+///
+/// * `s` - Markdown starting with backticks
+pub fn code_span_len(s: &str) -> usize {
+  let ticks = s.chars().take_while(|&c| c == '`').count();
+  match find_closing_ticks(&s[ticks..], ticks) {
+    Some(end) => ticks + end + ticks,
+    None => ticks,
+  }
+}
+
+/// Find the start of backtick run of exactly `ticks` length
+fn find_closing_ticks(s: &str, ticks: usize) -> Option<usize> {
+  let mut offset = 0;
+  while let Some(pos) = s[offset..].find('`') {
+    let start = offset + pos;
+    let len = s[start..].chars().take_while(|&c| c == '`').count();
+    if len == ticks {
+      return Some(start);
+    }
+    offset = start + len;
+  }
+
+  None
+}
@@ -0,0 +1,99 @@
+use std::collections::HashMap;
+
+use pulldown_cmark::{html, Event, Tag};
+
+/// Style of footnotes
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum FootnoteStyle {
+  /// Hatena's `((...))` notation, numbered and rendered by Hatena
+  Hatena,
+  /// Footnote section of pulldown-cmark
+  Html,
+}
+
+impl FootnoteStyle {
+  pub fn from(name: &str) -> Option<Self> {
+    match name {
+      "hatena" => Some(Self::Hatena),
+      "html" => Some(Self::Html),
+      _ => None,
+    }
+  }
+}
+
+/// Inline footnote definitions at their references in Hatena's `((...))` notation.
+///
+/// Definitions are removed from the document.
+/// Returns converted events and labels of references which have no definition.
+///
+/// # Arguments
+///
+/// * `events` - Events of whole document
+pub fn inline_footnotes(events: Vec<Event>) -> (Vec<Event>, Vec<String>) {
+  let mut definitions: HashMap<String, String> = HashMap::new();
+  let mut body = vec![];
+  let mut definition: Option<Vec<Event>> = None;
+
+  for event in events {
+    match event {
+      Event::Start(Tag::FootnoteDefinition(_)) => definition = Some(vec![]),
+      Event::End(Tag::FootnoteDefinition(label)) => {
+        let mut content = String::new();
+        html::push_html(
+          &mut content,
+          definition.take().unwrap_or_default().into_iter(),
+        );
+        definitions.insert(label.to_string(), flatten_paragraphs(&content));
+      }
+      event => match definition.as_mut() {
+        Some(definition) => definition.push(event),
+        None => body.push(event),
+      },
+    }
+  }
+
+  let mut undefined = vec![];
+  let body = body
+    .into_iter()
+    .map(|event| match event {
+      Event::FootnoteReference(label) => match definitions.get(label.as_ref()) {
+        Some(content) => Event::Html(format!("(({}))", content).into()),
+        None => {
+          undefined.push(label.to_string());
+          Event::FootnoteReference(label)
+        }
+      },
+      event => event,
+    })
+    .collect();
+
+  (body, undefined)
+}
+
+/// Flatten paragraphs into a single line, because Hatena's footnotes are inline
+fn flatten_paragraphs(html: &str) -> String {
+  html
+    .split("</p>")
+    .map(|paragraph| paragraph.trim().trim_start_matches("<p>").trim())
+    .filter(|paragraph| !paragraph.is_empty())
+    .collect::<Vec<_>>()
+    .join("<br>")
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use pulldown_cmark::{Options, Parser};
+
+  #[test]
+  fn test_inline_footnotes() {
+    let markdown = "Text[^1] and[^x].\n\n[^1]: See *this*.\n";
+    let events = Parser::new_ext(markdown, Options::all()).collect();
+    let (events, undefined) = inline_footnotes(events);
+    let mut converted = String::new();
+    html::push_html(&mut converted, events.into_iter());
+
+    assert!(converted.starts_with("<p>Text((See <em>this</em>.)) and<sup"));
+    assert_eq!(undefined, vec!["x"]);
+  }
+}
@@ -0,0 +1,131 @@
+use serde::{Deserialize, Deserializer};
+
+use super::options::HeadingDepth;
+
+/// YAML front matter at the start of HackMD note.
+///
+/// Besides HackMD's metadata, it can override converter options of `Config`.
+/// Unknown keys, such as HackMD's `lang` or `breaks`, are ignored.
+#[derive(Debug, Default, PartialEq, Deserialize, Clone)]
+pub struct FrontMatter {
+  pub title: Option<String>,
+  #[serde(default, deserialize_with = "deserialize_tags")]
+  pub tags: Vec<String>,
+  pub description: Option<String>,
+
+  pub heading_min: Option<HeadingDepth>,
+  pub codeblock_type: Option<String>,
+  pub math_mode: Option<String>,
+  pub indexing: Option<bool>,
+  pub indexing_depth: Option<usize>,
+  pub indexing_format: Option<String>,
+  pub toc_style: Option<String>,
+  pub footnote_type: Option<String>,
+}
+
+/// HackMD accepts both `tags: a, b` and `tags: [a, b]`
+fn deserialize_tags<'de, D>(deserializer: D) -> Result<Vec<String>, D::Error>
+where
+  D: Deserializer<'de>,
+{
+  #[derive(Deserialize)]
+  #[serde(untagged)]
+  enum Tags {
+    List(Vec<String>),
+    Csv(String),
+  }
+
+  Ok(match Option::<Tags>::deserialize(deserializer)? {
+    Some(Tags::List(tags)) => tags,
+    Some(Tags::Csv(tags)) => tags
+      .split(',')
+      .map(|tag| tag.trim().to_string())
+      .filter(|tag| !tag.is_empty())
+      .collect(),
+    None => vec![],
+  })
+}
+
+impl FrontMatter {
+  /// Parse front matter of markdown.
+  ///
+  /// Returns parsed front matter if exists, and markdown without it.
+  /// Front matter is replaced with blank lines, so that line numbers of the body are kept.
+  /// Invalid YAML is not front matter, such as a note starting with a horizontal rule,
+  /// and the markdown is returned as is with a warning.
+  ///
+  /// # Arguments
+  ///
+  /// * `markdown` - HackMD note
+  /// * `warnings` - Warnings to be pushed
+  pub fn parse(markdown: &str, warnings: &mut Vec<String>) -> (Option<Self>, String) {
+    let mut lines = markdown.lines();
+    if lines.next().map(|line| line.trim_end()) != Some("---") {
+      return (None, markdown.into());
+    }
+
+    let yaml = lines
+      .by_ref()
+      .take_while(|line| !matches!(line.trim_end(), "---" | "..."))
+      .collect::<Vec<_>>();
+    // Unterminated front matter is not front matter
+    if yaml.len() + 1 >= markdown.lines().count() {
+      return (None, markdown.into());
+    }
+
+    let front_matter: Self = if yaml.iter().all(|line| line.trim().is_empty()) {
+      Self::default()
+    } else {
+      match serde_yaml::from_str(&yaml.join("\n")) {
+        Ok(front_matter) => front_matter,
+        Err(e) => {
+          warnings.push(format!(
+            "front matter is ignored, because it is not valid YAML: {}",
+            e
+          ));
+          return (None, markdown.into());
+        }
+      }
+    };
+    let body = "\n".repeat(yaml.len() + 2) + &lines.collect::<Vec<_>>().join("\n");
+
+    (Some(front_matter), body)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_parse_front_matter() {
+    let markdown =
+      "---\ntitle: Hello\ntags: rust, hatena\nheading_min: 3\nlang: ja-jp\n---\n# Body";
+    let mut warnings = vec![];
+    let (front_matter, body) = FrontMatter::parse(markdown, &mut warnings);
+
+    assert_eq!(
+      front_matter,
+      Some(FrontMatter {
+        title: Some("Hello".into()),
+        tags: vec!["rust".into(), "hatena".into()],
+        heading_min: Some(HeadingDepth::new(3)),
+        ..Default::default()
+      })
+    );
+    assert_eq!(body, "\n\n\n\n\n\n# Body");
+    assert_eq!(
+      FrontMatter::parse("---\nnot front matter", &mut warnings),
+      (None, "---\nnot front matter".into())
+    );
+    assert!(warnings.is_empty());
+
+    // Horizontal rules around a paragraph are not front matter
+    let markdown = "---\nSee: [docs](https://example.com), and more.\n---\n# Body";
+    assert_eq!(
+      FrontMatter::parse(markdown, &mut warnings),
+      (None, markdown.into())
+    );
+    assert_eq!(warnings.len(), 1);
+  }
+}
@@ -1,6 +1,30 @@
-use std::io::prelude::*;
+pub mod fotolife;
+pub mod layout;
+pub mod process;
+pub mod size;
+pub mod source;
+pub mod store;
+
+use self::{fotolife::FotolifeImage, source::ImageSource, store::ImageStore};
+
+/// Representation of images uploaded to Fotolife
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum ImageStyle {
+  /// `<figure>` and `<img>` pointing to CDN URL
+  Html,
+  /// Hatena's Fotolife notation, `[f:id:user:20221010123456p:plain]`
+  Fotolife,
+}
 
-use crate::error::ApplicationError;
+impl ImageStyle {
+  pub fn from(name: &str) -> Option<Self> {
+    match name {
+      "html" => Some(Self::Html),
+      "fotolife" => Some(Self::Fotolife),
+      _ => None,
+    }
+  }
+}
 
 #[derive(Debug, PartialEq, Clone)]
 pub struct ResolvedImage {
@@ -24,103 +48,26 @@ impl ResolvedImage {
       .collect()
   }
 
-  pub fn cache_to(images: &Vec<Self>, cache_path: &str) -> Result<(), ApplicationError> {
-    let cache_path = std::path::Path::new(cache_path);
-
-    // Remove duplicating images
-    let existing_images = if cache_path.exists() {
-      Self::restore_from(&cache_path.to_string_lossy().to_string())?
-    } else {
-      vec![]
-    };
-    let images = images
-      .iter()
-      .filter(|image| !existing_images.contains(image))
-      .collect::<Vec<_>>();
-
-    // Convert to string
-    let cache_data: Vec<String> = images
+  /// Resolve images using cached ones, which are keyed by `ImageSource::cache_key`
+  ///
+  /// Fotolife URLs cached with a wrong directory of Hatena ID are corrected.
+  ///
+  /// # Arguments
+  ///
+  /// * `images` - URLs of images written in markdown, and their sources
+  /// * `store` - Store of images uploaded to Fotolife
+  pub fn resolve_cached(images: &[(String, ImageSource)], store: &ImageStore) -> Vec<Self> {
+    images
       .iter()
-      .map(|image| format!("{} -> {}", image.original_url, image.fotolife_url,))
-      .collect();
-
-    // Write to cache file
-    let mut file = std::fs::OpenOptions::new()
-      .write(true)
-      .append(true)
-      .create(true)
-      .open(cache_path)?;
-    for data in cache_data {
-      writeln!(file, "{}", data)?;
-    }
-
-    Ok(())
-  }
-
-  pub fn restore_from(cache_path: &str) -> Result<Vec<ResolvedImage>, ApplicationError> {
-    let cache_path = std::path::Path::new(cache_path);
-    let contents = if let Ok(contents) = std::fs::read_to_string(cache_path) {
-      contents
-    } else {
-      return Ok(vec![]);
-    };
-
-    let mut images: Vec<ResolvedImage> = vec![];
-    for line in contents.lines() {
-      let mut iter = line.split(" -> ");
-      let original_url = iter.next().unwrap().to_string();
-      let fotolife_url = iter.next().unwrap().to_string();
-      images.push(ResolvedImage {
-        original_url,
-        fotolife_url,
-      });
-    }
-
-    Ok(images)
-  }
-}
-
-#[cfg(test)]
-mod tests {
-  use super::*;
-
-  fn trim_indent(s: &str) -> String {
-    let mut lines = vec![];
-    for line in s.lines() {
-      if line.trim().is_empty() {
-        continue;
-      }
-      lines.push(line.trim());
-    }
-
-    lines.join("\n")
-  }
-
-  #[test]
-  fn test_cache_images() {
-    let resolved_images = vec![
-      ResolvedImage {
-        original_url: "https://example.com/image1.png".into(),
-        fotolife_url: "https://f.hatena.ne.jp/username/20200101/1234567890.png".into(),
-      },
-      ResolvedImage {
-        original_url: "https://example.com/image2.png".into(),
-        fotolife_url: "https://f.hatena.ne.jp/username/20200101/1234567891.png".into(),
-      },
-    ];
-    let cache_path = ".cache";
-
-    ResolvedImage::cache_to(&resolved_images, cache_path).unwrap();
-
-    let cache_content = std::fs::read_to_string(cache_path).unwrap();
-    assert_eq!(
-      cache_content.trim(),
-      trim_indent(
-        "
-        https://example.com/image1.png -> https://f.hatena.ne.jp/username/20200101/1234567890.png
-        https://example.com/image2.png -> https://f.hatena.ne.jp/username/20200101/1234567891.png
-      "
-      )
-    );
+      .filter_map(|(url, source)| {
+        let key = source.cache_key().ok()?;
+        store.find(&key).map(|image| ResolvedImage {
+          original_url: url.clone(),
+          fotolife_url: FotolifeImage::from_url(&image.fotolife_url)
+            .and_then(|image| image.to_url())
+            .unwrap_or_else(|| image.fotolife_url.clone()),
+        })
+      })
+      .collect()
   }
 }
@@ -0,0 +1,153 @@
+/// Origin and path prefix of images served by Hatena Fotolife
+const CDN_PREFIX: &str = "https://cdn-ak.f.st-hatena.com/images/fotolife/";
+
+/// Type of image stored in Fotolife, which is suffixed to the image ID in Fotolife notation
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum FotolifeType {
+  Jpeg,
+  Png,
+  Gif,
+}
+
+impl FotolifeType {
+  /// Type of image from the extension of its URL or file
+  pub fn from_extension(extension: &str) -> Option<Self> {
+    match extension {
+      "jpg" | "jpeg" => Some(Self::Jpeg),
+      "png" => Some(Self::Png),
+      "gif" => Some(Self::Gif),
+      _ => None,
+    }
+  }
+
+  /// Character following the image ID in Fotolife notation
+  pub fn to_char(self) -> char {
+    match self {
+      Self::Jpeg => 'j',
+      Self::Png => 'p',
+      Self::Gif => 'g',
+    }
+  }
+
+  /// Extension of the image served by CDN
+  pub fn extension(self) -> &'static str {
+    match self {
+      Self::Jpeg => "jpg",
+      Self::Png => "png",
+      Self::Gif => "gif",
+    }
+  }
+}
+
+/// Image uploaded to Hatena Fotolife
+#[derive(Debug, PartialEq, Clone)]
+pub struct FotolifeImage {
+  /// Hatena ID of the owner
+  pub user: String,
+  /// Image ID, which is the upload time such as `20221010123456`
+  pub image_id: String,
+  pub kind: FotolifeType,
+}
+
+impl FotolifeImage {
+  /// Parse CDN URL of Fotolife image, such as
+  /// `https://cdn-ak.f.st-hatena.com/images/fotolife/s/smallkirby/20221010/20221010123456.png`.
+  ///
+  /// The directory of the first letter of Hatena ID is not checked,
+  /// so that URLs with a wrong one can be corrected by `to_url`.
+  pub fn from_url(url: &str) -> Option<Self> {
+    let mut segments = url.strip_prefix(CDN_PREFIX)?.split('/');
+    let (_, user, dir, file) = (
+      segments.next()?,
+      segments.next()?,
+      segments.next()?,
+      segments.next()?,
+    );
+    if segments.next().is_some() {
+      return None;
+    }
+    let (image_id, extension) = file.split_once('.')?;
+    if user.is_empty() || !image_id.starts_with(dir) || image_id.len() < 8 {
+      return None;
+    }
+
+    Some(Self {
+      user: user.into(),
+      image_id: image_id.into(),
+      kind: FotolifeType::from_extension(extension)?,
+    })
+  }
+
+  /// CDN URL of the image, or `None` if the user or image ID is too short
+  pub fn to_url(&self) -> Option<String> {
+    cdn_url(&self.user, &self.image_id, self.kind.extension())
+  }
+
+  /// Fotolife notation without brackets and options, such as `f:id:smallkirby:20221010123456p`
+  pub fn to_notation(&self) -> String {
+    format!(
+      "f:id:{}:{}{}",
+      self.user,
+      self.image_id,
+      self.kind.to_char()
+    )
+  }
+}
+
+/// CDN URL of Fotolife image, which is placed under the first letter of Hatena ID
+/// and the date of the image ID.
+///
+/// Returns `None` if the user is empty or the image ID does not start with a date.
+///
+/// # Arguments
+///
+/// * `user` - Hatena ID of the owner
+/// * `image_id` - Image ID such as `20221010123456`
+/// * `extension` - Extension of the stored image
+pub fn cdn_url(user: &str, image_id: &str, extension: &str) -> Option<String> {
+  let initial = user.chars().next()?;
+  let date = image_id.get(..8)?;
+
+  Some(format!(
+    "{}{}/{}/{}/{}.{}",
+    CDN_PREFIX, initial, user, date, image_id, extension
+  ))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_fotolife_url() {
+    let image = FotolifeImage {
+      user: "nekoneko".into(),
+      image_id: "20221010123456".into(),
+      kind: FotolifeType::Jpeg,
+    };
+    let url =
+      "https://cdn-ak.f.st-hatena.com/images/fotolife/n/nekoneko/20221010/20221010123456.jpg";
+    assert_eq!(image.to_url().unwrap(), url);
+    assert_eq!(image.to_notation(), "f:id:nekoneko:20221010123456j");
+    assert_eq!(FotolifeImage::from_url(url), Some(image.clone()));
+
+    // URL with the wrong directory is corrected
+    let broken = url.replace("/n/nekoneko/", "/s/nekoneko/");
+    assert_eq!(
+      FotolifeImage::from_url(&broken).unwrap().to_url().unwrap(),
+      url
+    );
+
+    assert_eq!(FotolifeImage::from_url("https://example.com/a.png"), None);
+    assert_eq!(FotolifeImage::from_url(&url.replace(".jpg", ".bmp")), None);
+
+    // Short or multi-byte IDs never panic
+    assert_eq!(cdn_url("", "20221010123456", "png"), None);
+    assert_eq!(cdn_url("nekoneko", "2022", "png"), None);
+    assert_eq!(cdn_url("nekoneko", "202210年10", "png"), None);
+    assert_eq!(
+      cdn_url("ねこ", "20221010123456", "png").unwrap(),
+      "https://cdn-ak.f.st-hatena.com/images/fotolife/ね/ねこ/20221010/20221010123456.png"
+    );
+  }
+}
@@ -0,0 +1,156 @@
+use super::size::ImageSize;
+use crate::util::escape_html;
+
+/// Horizontal alignment of image
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum ImageAlign {
+  Left,
+  Center,
+  Right,
+}
+
+impl ImageAlign {
+  pub fn from(name: &str) -> Option<Self> {
+    match name {
+      "left" => Some(Self::Left),
+      "center" => Some(Self::Center),
+      "right" => Some(Self::Right),
+      _ => None,
+    }
+  }
+
+  fn as_str(self) -> &'static str {
+    match self {
+      Self::Left => "left",
+      Self::Center => "center",
+      Self::Right => "right",
+    }
+  }
+}
+
+/// Size, alignment and caption of image.
+///
+/// They are given by HackMD's size syntax and the title attribute of image,
+/// such as `![alt](url =400x "A cat {align=center}")`.
+/// Text of the title before `{...}` is the caption,
+/// and `width` and `align` inside braces precede the size syntax.
+/// Unknown keys inside braces are ignored.
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct ImageLayout {
+  pub size: ImageSize,
+  pub align: Option<ImageAlign>,
+  pub caption: Option<String>,
+}
+
+impl ImageLayout {
+  /// Parse layout of image
+  ///
+  /// # Arguments
+  ///
+  /// * `title` - Title attribute of image
+  /// * `size` - Size given by HackMD's size syntax
+  pub fn parse(title: &str, size: Option<ImageSize>) -> Self {
+    let mut layout = Self {
+      size: size.unwrap_or_default(),
+      ..Self::default()
+    };
+
+    let title = title.trim();
+    let (caption, options) = match title.strip_suffix('}').and_then(|t| t.rsplit_once('{')) {
+      Some((caption, options)) => (caption.trim_end(), options),
+      None => (title, ""),
+    };
+    for (key, value) in options.split_whitespace().filter_map(|o| o.split_once('=')) {
+      match key {
+        "width" => {
+          if let Ok(width) = value.trim_end_matches("px").parse() {
+            // Height follows the aspect ratio
+            layout.size = ImageSize {
+              width: Some(width),
+              height: None,
+            };
+          }
+        }
+        "align" => layout.align = ImageAlign::from(value),
+        _ => (),
+      }
+    }
+    if !caption.is_empty() {
+      layout.caption = Some(caption.into());
+    }
+
+    layout
+  }
+
+  /// Returns `style` attribute of `<figure>` for alignment, starting with a space
+  pub fn to_style(&self) -> String {
+    match self.align {
+      Some(align) => format!(r#" style="text-align: {}""#, align.as_str()),
+      None => "".into(),
+    }
+  }
+
+  /// Returns options of Fotolife notation following `:plain`, such as `:w400:title=A cat`
+  ///
+  /// # Arguments
+  ///
+  /// * `title` - Title of image, which is usually its caption or alt text
+  pub fn to_fotolife_options(&self, title: &str) -> String {
+    let mut options = self.size.to_fotolife_suffix();
+    // Brackets and colons would end the notation or the option
+    let title = title.replace(['[', ']', ':'], "");
+    if !title.trim().is_empty() {
+      options.push_str(&format!(":title={}", escape_html(title.trim())));
+    }
+    options
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_parse_layout() {
+    let size = ImageSize::parse("=300x200");
+    assert_eq!(
+      ImageLayout::parse("", size),
+      ImageLayout {
+        size: size.unwrap(),
+        ..ImageLayout::default()
+      }
+    );
+    assert_eq!(
+      ImageLayout::parse("A cat {width=400px align=center}", size),
+      ImageLayout {
+        size: ImageSize {
+          width: Some(400),
+          height: None,
+        },
+        align: Some(ImageAlign::Center),
+        caption: Some("A cat".into()),
+      }
+    );
+    assert_eq!(
+      ImageLayout::parse("{align=right unknown=1}", None),
+      ImageLayout {
+        align: Some(ImageAlign::Right),
+        ..ImageLayout::default()
+      }
+    );
+    // Braces not at the end are a part of caption
+    assert_eq!(
+      ImageLayout::parse("f(x) = {x} + 1", None)
+        .caption
+        .as_deref(),
+      Some("f(x) = {x} + 1")
+    );
+
+    let layout = ImageLayout::parse("Fig. 1: [draft] {width=400 align=left}", None);
+    assert_eq!(layout.to_style(), r#" style="text-align: left""#);
+    assert_eq!(
+      layout.to_fotolife_options(layout.caption.as_deref().unwrap()),
+      ":w400:title=Fig. 1 draft"
+    );
+  }
+}
@@ -0,0 +1,254 @@
+use std::{
+  fs::File,
+  io::BufWriter,
+  path::{Path, PathBuf},
+};
+
+use image::{
+  codecs::{jpeg::JpegEncoder, png::PngEncoder},
+  imageops::FilterType,
+  DynamicImage, ImageDecoder, ImageError, ImageReader, Rgba, RgbaImage,
+};
+
+use sha2::{Digest, Sha256};
+
+use super::source::stored_extension;
+use crate::{config::Config, error::ApplicationError};
+
+/// Quality of JPEG and WebP images if `image_quality` is not given
+const DEFAULT_QUALITY: u8 = 85;
+
+/// Format of processed images
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum ImageFormat {
+  /// Keep the format of the original image
+  Original,
+  /// Convert PNG into JPEG at `image_quality`
+  Jpeg,
+  /// Convert PNG into lossy WebP at `image_quality`
+  Webp,
+}
+
+impl ImageFormat {
+  pub fn from(name: &str) -> Option<Self> {
+    match name {
+      "original" => Some(Self::Original),
+      "jpeg" | "jpg" => Some(Self::Jpeg),
+      "webp" => Some(Self::Webp),
+      _ => None,
+    }
+  }
+}
+
+/// Preprocessor of images applied before uploading to Fotolife
+#[derive(Debug, PartialEq, Clone)]
+pub struct ImageProcessor {
+  max_width: Option<u32>,
+  format: ImageFormat,
+  quality: u8,
+  strip_metadata: bool,
+}
+
+impl ImageProcessor {
+  /// Create new ImageProcessor.
+  ///
+  /// Returns error if `image_format` is unknown or `image_quality` is not from 1 to 100.
+  pub fn new(config: &Config) -> Result<Self, ApplicationError> {
+    let format =
+      ImageFormat::from(&config.image_format).ok_or_else(|| ApplicationError::MiscError {
+        message: format!(
+          "`image_format` must be `original`, `jpeg` or `webp`, but `{}` is given",
+          config.image_format
+        ),
+      })?;
+    let quality = config.image_quality.unwrap_or(DEFAULT_QUALITY);
+    if !(1..=100).contains(&quality) {
+      return Err(ApplicationError::MiscError {
+        message: format!(
+          "`image_quality` must be from 1 to 100, but {} is given",
+          quality
+        ),
+      });
+    }
+
+    Ok(Self {
+      max_width: config.image_max_width,
+      format,
+      quality,
+      strip_metadata: config.image_strip_metadata,
+    })
+  }
+
+  /// Whether the processor changes no images
+  pub fn is_noop(&self) -> bool {
+    self.max_width.is_none() && self.format == ImageFormat::Original && !self.strip_metadata
+  }
+
+  /// Process image and returns path of the processed copy.
+  ///
+  /// The copy is written into `output_dir` as `<name>.<hash>.processed.<extension>`,
+  /// where `<hash>` is derived from the original path to tell apart images of the same name.
+  /// Images other than PNG, JPEG and WebP, and images needing no change are not copied,
+  /// and the original path is returned.
+  /// Format of images without extension is guessed from their content.
+  /// Re-encoding drops all metadata, so EXIF orientation is applied to pixels beforehand.
+  ///
+  /// # Arguments
+  ///
+  /// * `path` - Path of the original image
+  /// * `output_dir` - Directory to write the processed copy
+  pub fn process(&self, path: &Path, output_dir: &Path) -> Result<PathBuf, ImageError> {
+    // Images without extension are told by their content
+    let extension = match path.extension() {
+      Some(extension) => extension.to_string_lossy().to_lowercase(),
+      None => stored_extension(path)?.unwrap_or_default(),
+    };
+    let is_png = match extension.as_str() {
+      "png" => true,
+      "jpg" | "jpeg" | "webp" => false,
+      _ => return Ok(path.to_path_buf()),
+    };
+    if self.is_noop() {
+      return Ok(path.to_path_buf());
+    }
+
+    let mut decoder = ImageReader::open(path)?
+      .with_guessed_format()?
+      .into_decoder()?;
+    let orientation = decoder.orientation()?;
+    let mut image = DynamicImage::from_decoder(decoder)?;
+    image.apply_orientation(orientation);
+
+    let resized = match self.max_width {
+      Some(max_width) if image.width() > max_width => {
+        image = image.resize(max_width, u32::MAX, FilterType::Lanczos3);
+        true
+      }
+      _ => false,
+    };
+    let converted = is_png && self.format != ImageFormat::Original;
+    if !(resized || converted || self.strip_metadata) {
+      return Ok(path.to_path_buf());
+    }
+
+    let format = match (self.format, is_png) {
+      (ImageFormat::Jpeg, true) => "jpg",
+      (ImageFormat::Webp, true) => "webp",
+      _ => extension.as_str(),
+    };
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let path_hash = format!("{:x}", Sha256::digest(path.to_string_lossy().as_bytes()));
+    let output = output_dir.join(format!("{}.{}.processed.{}", stem, &path_hash[..8], format));
+    std::fs::create_dir_all(output_dir)?;
+
+    match format {
+      "jpg" | "jpeg" => {
+        let writer = BufWriter::new(File::create(&output)?);
+        let image = DynamicImage::ImageRgb8(flatten_alpha(&image));
+        image.write_with_encoder(JpegEncoder::new_with_quality(writer, self.quality))?
+      }
+      // `image` encodes only lossless WebP
+      "webp" => {
+        let image = image.to_rgba8();
+        let encoded = webp::Encoder::from_rgba(&image, image.width(), image.height())
+          .encode(self.quality as f32);
+        std::fs::write(&output, &*encoded)?
+      }
+      _ => {
+        let writer = BufWriter::new(File::create(&output)?);
+        image.write_with_encoder(PngEncoder::new(writer))?
+      }
+    }
+
+    Ok(output)
+  }
+}
+
+/// Composite transparent pixels on white background, because JPEG has no alpha channel
+fn flatten_alpha(image: &DynamicImage) -> image::RgbImage {
+  let mut background = RgbaImage::from_pixel(image.width(), image.height(), Rgba([255; 4]));
+  image::imageops::overlay(&mut background, &image.to_rgba8(), 0, 0);
+  DynamicImage::ImageRgba8(background).to_rgb8()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_process_image() {
+    let dir = std::env::temp_dir().join("md2hatena-test-process-image");
+    std::fs::create_dir_all(&dir).unwrap();
+    let original = dir.join("screenshot.png");
+    RgbaImage::from_pixel(400, 200, Rgba([0, 0, 0, 0]))
+      .save(&original)
+      .unwrap();
+
+    let mut config = Config::new();
+    let processor = ImageProcessor::new(&config).unwrap();
+    assert!(processor.is_noop());
+    assert_eq!(processor.process(&original, &dir).unwrap(), original);
+
+    config.image_max_width = Some(100);
+    config.image_format = "jpeg".into();
+    let processed = ImageProcessor::new(&config)
+      .unwrap()
+      .process(&original, &dir)
+      .unwrap();
+    let name = processed.file_name().unwrap().to_string_lossy();
+    assert!(name.starts_with("screenshot.") && name.ends_with(".processed.jpg"));
+    let image = image::open(&processed).unwrap();
+    assert_eq!((image.width(), image.height()), (100, 50));
+    assert_eq!(image.to_rgb8().get_pixel(50, 25).0, [255, 255, 255]);
+
+    // Images of the same name in other directories are not overwritten
+    std::fs::create_dir_all(dir.join("other")).unwrap();
+    let other = dir.join("other/screenshot.png");
+    std::fs::copy(&original, &other).unwrap();
+    let other_processed = ImageProcessor::new(&config)
+      .unwrap()
+      .process(&other, &dir)
+      .unwrap();
+    assert_ne!(other_processed, processed);
+
+    // Format of images without extension is told by their content
+    let no_extension = dir.join("screenshot");
+    std::fs::copy(&original, &no_extension).unwrap();
+    config.image_format = "original".into();
+    let processed = ImageProcessor::new(&config)
+      .unwrap()
+      .process(&no_extension, &dir)
+      .unwrap();
+    assert!(processed.to_string_lossy().ends_with(".processed.png"));
+
+    // Quality is applied to lossy WebP
+    let photo = dir.join("photo.png");
+    image::RgbImage::from_fn(100, 100, |x, y| {
+      image::Rgb([(x * y) as u8, (x * 7) as u8, (y * 13) as u8])
+    })
+    .save(&photo)
+    .unwrap();
+    config.image_format = "webp".into();
+    config.image_quality = Some(10);
+    let low = ImageProcessor::new(&config)
+      .unwrap()
+      .process(&photo, &dir)
+      .unwrap();
+    assert!(low.to_string_lossy().ends_with(".processed.webp"));
+    let low_size = std::fs::metadata(&low).unwrap().len();
+    config.image_quality = Some(100);
+    let high = ImageProcessor::new(&config)
+      .unwrap()
+      .process(&photo, &dir)
+      .unwrap();
+    assert!(std::fs::metadata(&high).unwrap().len() > low_size);
+    assert_eq!(image::open(&high).unwrap().width(), 100);
+
+    // Invalid options are rejected before processing any image
+    config.image_quality = Some(0);
+    assert!(ImageProcessor::new(&config).is_err());
+    config.image_quality = None;
+    config.image_format = "gif".into();
+    assert!(ImageProcessor::new(&config).is_err());
+  }
+}
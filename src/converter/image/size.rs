@@ -0,0 +1,198 @@
+use crate::converter::fence::{code_span_len, FenceTracker};
+
+/// Size of image given by HackMD's `![alt](url =WxH)` syntax.
+///
+/// Either of width and height can be omitted, such as `=300x` and `=x200`.
+#[derive(Debug, PartialEq, Clone, Copy, Default)]
+pub struct ImageSize {
+  pub width: Option<u32>,
+  pub height: Option<u32>,
+}
+
+impl ImageSize {
+  /// Parse size suffix such as `=300x200`
+  pub fn parse(s: &str) -> Option<Self> {
+    let (width, height) = s.strip_prefix('=')?.split_once('x')?;
+    let parse = |n: &str| -> Option<Option<u32>> {
+      if n.is_empty() {
+        Some(None)
+      } else {
+        n.parse::<u32>().ok().map(Some)
+      }
+    };
+    let size = Self {
+      width: parse(width)?,
+      height: parse(height)?,
+    };
+
+    if size.width.is_none() && size.height.is_none() {
+      None
+    } else {
+      Some(size)
+    }
+  }
+
+  /// Split image destination into URL and size
+  ///
+  /// # Arguments
+  ///
+  /// * `dest` - Destination of image, such as `url =300x200`
+  pub fn split_url(dest: &str) -> (&str, Option<Self>) {
+    match dest.rsplit_once(' ') {
+      Some((url, size)) => match Self::parse(size) {
+        Some(size) => (url.trim_end(), Some(size)),
+        None => (dest, None),
+      },
+      None => (dest, None),
+    }
+  }
+
+  /// Returns `width` and `height` attributes of `<img>`, starting with a space
+  pub fn to_attributes(&self) -> String {
+    let mut attributes = String::new();
+    if let Some(width) = self.width {
+      attributes.push_str(&format!(r#" width="{}""#, width));
+    }
+    if let Some(height) = self.height {
+      attributes.push_str(&format!(r#" height="{}""#, height));
+    }
+    attributes
+  }
+
+  /// Returns size suffix of Hatena's Fotolife notation, such as `:w300`.
+  ///
+  /// Fotolife notation keeps aspect ratio, so width precedes height if both are given.
+  pub fn to_fotolife_suffix(&self) -> String {
+    match (self.width, self.height) {
+      (Some(width), _) => format!(":w{}", width),
+      (None, Some(height)) => format!(":h{}", height),
+      (None, None) => "".into(),
+    }
+  }
+}
+
+/// Wrap destinations of images having size suffix with `<>`.
+///
+/// pulldown-cmark does not allow spaces in link destinations,
+/// so `![alt](url =300x200)` is rewritten into `![alt](<url =300x200>)`.
+/// Then the destination can be split by `ImageSize::split_url`.
+///
+/// # Arguments
+///
+/// * `markdown` - HackMD note
+pub fn wrap_sized_images(markdown: &str) -> String {
+  let mut fence = FenceTracker::new();
+  let mut lines = vec![];
+
+  for line in markdown.lines() {
+    if fence.feed(line) {
+      lines.push(line.to_string());
+      continue;
+    }
+
+    let mut replaced = String::with_capacity(line.len());
+    let mut rest = line;
+    while let Some(pos) = rest.find(['`', '!']) {
+      replaced.push_str(&rest[..pos]);
+      rest = &rest[pos..];
+
+      if rest.starts_with('`') {
+        let len = code_span_len(rest);
+        replaced.push_str(&rest[..len]);
+        rest = &rest[len..];
+        continue;
+      }
+
+      match wrap_sized_image(rest) {
+        Some((image, len)) => {
+          replaced.push_str(&image);
+          rest = &rest[len..];
+        }
+        None => {
+          replaced.push('!');
+          rest = &rest[1..];
+        }
+      }
+    }
+    replaced.push_str(rest);
+
+    lines.push(replaced);
+  }
+
+  let mut wrapped = lines.join("\n");
+  wrapped.push('\n');
+  wrapped
+}
+
+/// Rewrite an image at the start of `s` if it has size suffix.
+///
+/// Returns rewritten image and length of the original one.
+fn wrap_sized_image(s: &str) -> Option<(String, usize)> {
+  let alt_end = s.strip_prefix("![")?.find("](")? + 2;
+  let dest_end = alt_end + 2 + closing_paren(&s[alt_end + 2..])?;
+  let inner = &s[alt_end + 2..dest_end];
+
+  let (rest, size) = inner.trim_end().rsplit_once(' ')?;
+  ImageSize::parse(size)?;
+  let rest = rest.trim();
+  let (url, title) = match rest.split_once(' ') {
+    Some((url, title)) => (url, format!(" {}", title.trim())),
+    None => (rest, "".into()),
+  };
+  if url.is_empty() || url.starts_with('<') {
+    return None;
+  }
+
+  Some((
+    format!("{}](<{} {}>{})", &s[..alt_end], url, size, title),
+    dest_end + 1,
+  ))
+}
+
+/// Find `)` closing a link destination.
+///
+/// Parentheses in the destination must be balanced, as CommonMark requires,
+/// such as `https://en.wikipedia.org/wiki/Foo_(bar)`. Escaped ones are ignored.
+fn closing_paren(s: &str) -> Option<usize> {
+  let mut depth = 0;
+  let mut escaped = false;
+  for (i, c) in s.char_indices() {
+    match c {
+      _ if escaped => escaped = false,
+      '\\' => escaped = true,
+      '(' => depth += 1,
+      ')' if depth == 0 => return Some(i),
+      ')' => depth -= 1,
+      _ => {}
+    }
+  }
+  None
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_image_size() {
+    assert_eq!(
+      wrap_sized_images(
+        "![a](https://example.com/a.png =300x200) ![b](b.png \"title\" =x100)\n`![c](c.png =1x1)`"
+      ),
+      "![a](<https://example.com/a.png =300x200>) ![b](<b.png =x100> \"title\")\n`![c](c.png =1x1)`\n"
+    );
+
+    assert_eq!(
+      wrap_sized_images("![](https://en.wikipedia.org/wiki/Foo_(bar).png =400x) (see)"),
+      "![](<https://en.wikipedia.org/wiki/Foo_(bar).png =400x>) (see)\n"
+    );
+
+    let (url, size) = ImageSize::split_url("https://example.com/a.png =300x");
+    assert_eq!(url, "https://example.com/a.png");
+    let size = size.unwrap();
+    assert_eq!(size.to_attributes(), r#" width="300""#);
+    assert_eq!(size.to_fotolife_suffix(), ":w300");
+    assert_eq!(ImageSize::split_url("a.png"), ("a.png", None));
+    assert_eq!(ImageSize::parse("=x"), None);
+  }
+}
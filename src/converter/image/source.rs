@@ -0,0 +1,265 @@
+use std::path::{Path, PathBuf};
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use percent_encoding::percent_decode_str;
+use sha2::{Digest, Sha256};
+
+/// Where an image comes from
+#[derive(Debug, PartialEq, Clone)]
+pub enum ImageSource {
+  /// Image on the network, which is downloaded into `download_dir`
+  Remote(String),
+  /// Image on the local filesystem, which is uploaded as it is
+  Local(PathBuf),
+  /// Image embedded as `data:` URI, which is decoded into `download_dir`
+  Data {
+    /// SHA-256 of the decoded content
+    hash: String,
+    extension: String,
+    content: Vec<u8>,
+  },
+}
+
+impl ImageSource {
+  /// Classify image URL written in markdown.
+  ///
+  /// URLs without `http(s)://` or `data:` are local paths, resolved relative to `base_dir`.
+  /// Returns error message if the URL is a malformed `data:` URI,
+  /// or a `data:` URI of SVG, which Fotolife does not accept.
+  ///
+  /// # Arguments
+  ///
+  /// * `url` - URL of image written in markdown
+  /// * `base_dir` - Directory containing the markdown file
+  pub fn from(url: &str, base_dir: &Path) -> Result<Self, String> {
+    if url.starts_with("http://") || url.starts_with("https://") {
+      return Ok(Self::Remote(url.into()));
+    }
+    if let Some(data) = url.strip_prefix("data:") {
+      return Self::from_data_uri(data);
+    }
+
+    let path = url.strip_prefix("file://").unwrap_or(url);
+    let path = percent_decode_str(path).decode_utf8_lossy();
+    Ok(Self::Local(base_dir.join(path.as_ref())))
+  }
+
+  /// Decode `data:` URI, such as `image/png;base64,...`
+  fn from_data_uri(data: &str) -> Result<Self, String> {
+    let (header, payload) = data
+      .split_once(',')
+      .ok_or_else(|| "data URI has no content".to_string())?;
+    let (mime, is_base64) = match header.strip_suffix(";base64") {
+      Some(mime) => (mime, true),
+      None => (header, false),
+    };
+    let subtype = mime
+      .split(';')
+      .next()
+      .and_then(|mime| mime.strip_prefix("image/"))
+      .ok_or_else(|| format!("data URI of `{}` is not an image", mime))?;
+    if subtype == "svg+xml" {
+      return Err("data URI of SVG is not supported by Fotolife".into());
+    }
+    let extension = match subtype.split('+').next().unwrap() {
+      "jpeg" => "jpg",
+      subtype => subtype,
+    };
+
+    let content = if is_base64 {
+      let payload = payload
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .collect::<String>();
+      STANDARD
+        .decode(payload)
+        .map_err(|e| format!("failed to decode data URI: {}", e))?
+    } else {
+      percent_decode_str(payload).collect()
+    };
+
+    Ok(Self::Data {
+      hash: format!("{:x}", Sha256::digest(&content)),
+      extension: extension.into(),
+      content,
+    })
+  }
+
+  /// Whether the image needs to be downloaded before uploading
+  pub fn is_remote(&self) -> bool {
+    matches!(self, Self::Remote(_))
+  }
+
+  /// Path of the image file to upload
+  ///
+  /// # Arguments
+  ///
+  /// * `download_dir` - Directory where remote images are downloaded
+  pub fn path(&self, download_dir: &Path) -> PathBuf {
+    match self {
+      Self::Remote(url) => download_dir.join(download_name(url)),
+      Self::Local(path) => path.clone(),
+      Self::Data {
+        hash, extension, ..
+      } => download_dir.join(format!("{}.{}", hash, extension)),
+    }
+  }
+
+  /// Write decoded content of `data:` URI into `download_dir`.
+  ///
+  /// Images of other sources are not touched.
+  pub fn save(&self, download_dir: &Path) -> Result<(), std::io::Error> {
+    if let Self::Data { content, .. } = self {
+      std::fs::create_dir_all(download_dir)?;
+      std::fs::write(self.path(download_dir), content)?;
+    }
+    Ok(())
+  }
+
+  /// Key of the image in image mapping cache.
+  ///
+  /// Remote images are identified by their URL.
+  /// Local images are identified by their canonical path and SHA-256 of the content,
+  /// so that edited images are uploaded again.
+  /// `data:` URI images are identified only by SHA-256 of the content.
+  pub fn cache_key(&self) -> Result<String, std::io::Error> {
+    match self {
+      Self::Remote(url) => Ok(url.clone()),
+      Self::Local(path) => {
+        let path = path.canonicalize()?;
+        let hash = Sha256::digest(std::fs::read(&path)?);
+        Ok(format!("file://{}#sha256={:x}", path.display(), hash))
+      }
+      Self::Data { hash, .. } => Ok(format!("sha256:{}", hash)),
+    }
+  }
+}
+
+/// Name of the file where a remote image is downloaded, such as `<SHA-256 of URL>.png`.
+///
+/// Images of the same name on different hosts or directories are kept apart,
+/// and the extension of the URL is kept if any.
+pub fn download_name(url: &str) -> String {
+  let hash = format!("{:x}", Sha256::digest(url.as_bytes()));
+  let path = url.split(['?', '#']).next().unwrap_or_default();
+  let extension = path
+    .rsplit('/')
+    .next()
+    .and_then(|name| Path::new(name).extension())
+    .map(|extension| extension.to_string_lossy().to_string());
+  match extension {
+    Some(extension) => format!("{}.{}", hash, extension),
+    None => hash,
+  }
+}
+
+/// Returns SHA-256 of a file, such as `sha256:...`
+pub fn content_hash(path: &Path) -> Result<String, std::io::Error> {
+  Ok(format!("sha256:{:x}", Sha256::digest(std::fs::read(path)?)))
+}
+
+/// Extension of an image as Fotolife stores it, such as `png`.
+///
+/// Fotolife stores images in the format of their content, but does not tell it in the response.
+/// The format is guessed from the content, falling back to the extension of the file
+/// for formats which cannot be guessed, such as SVG.
+/// Returns `None` if neither tells the format.
+pub fn stored_extension(path: &Path) -> Result<Option<String>, std::io::Error> {
+  let guessed = image::guess_format(&std::fs::read(path)?)
+    .ok()
+    .and_then(|format| format.extensions_str().first().copied());
+  Ok(match guessed {
+    Some(extension) => Some(extension.into()),
+    None => path
+      .extension()
+      .map(|extension| extension.to_string_lossy().to_lowercase()),
+  })
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_image_source() {
+    let base_dir = std::env::temp_dir().join("md2hatena-test-image-source");
+    std::fs::create_dir_all(base_dir.join("img")).unwrap();
+    std::fs::write(base_dir.join("img/my diagram.png"), b"image").unwrap();
+
+    let remote = ImageSource::from("https://example.com/a.png", &base_dir).unwrap();
+    assert_eq!(
+      remote,
+      ImageSource::Remote("https://example.com/a.png".into())
+    );
+    assert_eq!(
+      remote.path(Path::new("/tmp/dl")),
+      PathBuf::from("/tmp/dl/494a30704d4f32ac0b81739d18a66d3638d440cbc6f5669f6af66f840edee5ab.png")
+    );
+    // Images of the same name are downloaded into different files
+    let (a, b) = (
+      ImageSource::from("https://a.com/x/image.png", &base_dir).unwrap(),
+      ImageSource::from("https://b.com/y/image.png?size=large", &base_dir).unwrap(),
+    );
+    assert_ne!(a.path(&base_dir), b.path(&base_dir));
+    assert!(b.path(&base_dir).to_string_lossy().ends_with(".png"));
+    assert_eq!(remote.cache_key().unwrap(), "https://example.com/a.png");
+
+    let local = ImageSource::from("./img/my%20diagram.png", &base_dir).unwrap();
+    assert!(!local.is_remote());
+    assert_eq!(
+      local.path(Path::new("/tmp/dl")),
+      base_dir.join("./img/my diagram.png")
+    );
+    let key = local.cache_key().unwrap();
+    assert!(key.starts_with("file://") && key.contains("my diagram.png#sha256="));
+    assert!(key.ends_with("6105d6cc76af400325e94d588ce511be5bfdbb73b437dc51eca43917d7a43e3d"));
+
+    assert_eq!(
+      ImageSource::from("/abs/a.png", &base_dir).unwrap(),
+      ImageSource::Local(PathBuf::from("/abs/a.png"))
+    );
+
+    // "image" in base64
+    let data = ImageSource::from("data:image/png;base64,aW1h\nZ2U=", &base_dir).unwrap();
+    assert_eq!(
+      data.path(Path::new("/tmp/dl")),
+      PathBuf::from("/tmp/dl/6105d6cc76af400325e94d588ce511be5bfdbb73b437dc51eca43917d7a43e3d.png")
+    );
+    assert_eq!(
+      data.cache_key().unwrap(),
+      "sha256:6105d6cc76af400325e94d588ce511be5bfdbb73b437dc51eca43917d7a43e3d"
+    );
+    let gif = ImageSource::from("data:image/gif,GIF89a", &base_dir).unwrap();
+    assert!(matches!(gif, ImageSource::Data { extension, content, .. }
+      if extension == "gif" && content == b"GIF89a"));
+    assert!(ImageSource::from("data:image/svg+xml,%3Csvg%3E", &base_dir).is_err());
+    assert!(ImageSource::from("data:text/plain,hello", &base_dir).is_err());
+  }
+
+  #[test]
+  fn test_stored_extension() {
+    let dir = std::env::temp_dir().join("md2hatena-test-stored-extension");
+    std::fs::create_dir_all(&dir).unwrap();
+    let png = dir.join("photo.JPG");
+    image::RgbImage::new(1, 1)
+      .save_with_format(&png, image::ImageFormat::Png)
+      .unwrap();
+    let unknown = dir.join("diagram.SVG");
+    std::fs::write(&unknown, b"<svg></svg>").unwrap();
+
+    // Content precedes extension, which is used only if the content is unknown
+    assert_eq!(stored_extension(&png).unwrap().as_deref(), Some("png"));
+    assert_eq!(stored_extension(&unknown).unwrap().as_deref(), Some("svg"));
+    assert!(stored_extension(&dir.join("missing.png")).is_err());
+
+    // Files without extension are told only by their content
+    let no_extension = dir.join("photo");
+    std::fs::copy(&png, &no_extension).unwrap();
+    assert_eq!(
+      stored_extension(&no_extension).unwrap().as_deref(),
+      Some("png")
+    );
+    std::fs::write(&no_extension, b"<svg></svg>").unwrap();
+    assert_eq!(stored_extension(&no_extension).unwrap(), None);
+  }
+}
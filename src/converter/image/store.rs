@@ -0,0 +1,231 @@
+use std::path::Path;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+  error::ApplicationError,
+  store::{self, VersionedStore},
+};
+
+/// Image uploaded to Hatena Fotolife
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct StoredImage {
+  /// Key of the image, given by `ImageSource::cache_key`
+  pub original_url: String,
+  /// SHA-256 of the original content, such as `sha256:...`
+  #[serde(default)]
+  pub content_hash: Option<String>,
+  #[serde(default)]
+  pub fotolife_id: Option<String>,
+  pub fotolife_url: String,
+  #[serde(default)]
+  pub uploaded_at: Option<DateTime<Utc>>,
+  /// Size in bytes of the uploaded file
+  #[serde(default)]
+  pub size: Option<u64>,
+  /// Paths of markdown files referencing the image
+  #[serde(default)]
+  pub documents: Vec<String>,
+}
+
+impl StoredImage {
+  /// Create an entry without metadata
+  pub fn new(original_url: &str, fotolife_url: &str) -> Self {
+    Self {
+      original_url: original_url.into(),
+      content_hash: None,
+      fotolife_id: None,
+      fotolife_url: fotolife_url.into(),
+      uploaded_at: None,
+      size: None,
+      documents: vec![],
+    }
+  }
+}
+
+/// Versioned JSON store of images uploaded to Hatena Fotolife
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct ImageStore {
+  pub version: u32,
+  pub images: Vec<StoredImage>,
+}
+
+impl Default for ImageStore {
+  fn default() -> Self {
+    Self {
+      version: Self::VERSION,
+      images: vec![],
+    }
+  }
+}
+
+impl VersionedStore for ImageStore {
+  const VERSION: u32 = 1;
+  const NAME: &'static str = "image store";
+
+  fn version(&self) -> u32 {
+    self.version
+  }
+
+  /// Open image store, or returns empty one if the file does not exist.
+  ///
+  /// Legacy text format (`original -> fotolife` lines) is migrated automatically.
+  /// The migrated store is written back, and the legacy file is kept as `<path>.legacy`.
+  ///
+  /// # Arguments
+  ///
+  /// * `path` - Path to image store
+  fn open(path: &str) -> Result<Self, ApplicationError> {
+    let path = Path::new(path);
+    let contents = match store::read(path)? {
+      Some(contents) => contents,
+      None => return Ok(Self::new()),
+    };
+    if contents.trim_start().starts_with('{') {
+      return Self::from_json(&contents);
+    }
+
+    let store = Self::from_legacy(&contents);
+    let mut legacy_path = path.as_os_str().to_owned();
+    legacy_path.push(".legacy");
+    std::fs::copy(path, legacy_path)?;
+    store.save(&path.to_string_lossy())?;
+
+    Ok(store)
+  }
+}
+
+impl ImageStore {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  /// Parse legacy text format.
+  ///
+  /// Lines are `original -> fotolife`, optionally followed by ` -> sha256:...`.
+  /// Since original URL can contain ` -> `, fields are split from the end.
+  /// Malformed lines are skipped.
+  fn from_legacy(contents: &str) -> Self {
+    let mut store = Self::new();
+    for line in contents.lines() {
+      let (rest, content_hash) = match line.rsplit_once(" -> ") {
+        Some((rest, hash)) if hash.starts_with("sha256:") => (rest, Some(hash.to_string())),
+        _ => (line, None),
+      };
+      if let Some((original_url, fotolife_url)) = rest.rsplit_once(" -> ") {
+        store.insert(StoredImage {
+          content_hash,
+          ..StoredImage::new(original_url.trim(), fotolife_url.trim())
+        });
+      }
+    }
+
+    store
+  }
+
+  /// Find an image by its key
+  pub fn find(&self, original_url: &str) -> Option<&StoredImage> {
+    self
+      .images
+      .iter()
+      .find(|image| image.original_url == original_url)
+  }
+
+  /// Find an image having the same content
+  ///
+  /// # Arguments
+  ///
+  /// * `content_hash` - Hash of the image content, such as `sha256:...`
+  pub fn find_by_hash(&self, content_hash: &str) -> Option<&StoredImage> {
+    self
+      .images
+      .iter()
+      .find(|image| image.content_hash.as_deref() == Some(content_hash))
+  }
+
+  /// Insert an image, or update the entry having the same key.
+  ///
+  /// Documents referencing the image are merged.
+  pub fn insert(&mut self, image: StoredImage) {
+    match self
+      .images
+      .iter_mut()
+      .find(|stored| stored.original_url == image.original_url)
+    {
+      Some(stored) => {
+        let mut documents = std::mem::take(&mut stored.documents);
+        for document in &image.documents {
+          if !documents.contains(document) {
+            documents.push(document.clone());
+          }
+        }
+        *stored = StoredImage { documents, ..image };
+      }
+      None => self.images.push(image),
+    }
+  }
+
+  /// Record that a document references an image
+  pub fn reference(&mut self, original_url: &str, document: &str) {
+    if let Some(image) = self
+      .images
+      .iter_mut()
+      .find(|image| image.original_url == original_url)
+    {
+      if !image.documents.iter().any(|d| d == document) {
+        image.documents.push(document.into());
+      }
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_migrate_legacy_store() {
+    let dir = std::env::temp_dir().join("md2hatena-test-image-store");
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("images.mapping");
+    std::fs::write(
+      &path,
+      "https://example.com/a.png -> https://cdn/a.png\n\
+       https://example.com/?q=x -> y -> https://cdn/b.png -> sha256:abcd\n\
+       malformed line\n",
+    )
+    .unwrap();
+
+    let mut store = ImageStore::open(&path.to_string_lossy()).unwrap();
+    assert_eq!(
+      store.images,
+      vec![
+        StoredImage::new("https://example.com/a.png", "https://cdn/a.png"),
+        StoredImage {
+          content_hash: Some("sha256:abcd".into()),
+          ..StoredImage::new("https://example.com/?q=x -> y", "https://cdn/b.png")
+        },
+      ]
+    );
+    assert!(dir.join("images.mapping.legacy").exists());
+    assert_eq!(
+      store.find_by_hash("sha256:abcd").unwrap().fotolife_url,
+      "https://cdn/b.png"
+    );
+
+    store.reference("https://example.com/a.png", "note.md");
+    store.insert(StoredImage {
+      size: Some(42),
+      documents: vec!["other.md".into()],
+      ..StoredImage::new("https://example.com/a.png", "https://cdn/a2.png")
+    });
+    store.save(&path.to_string_lossy()).unwrap();
+
+    let reopened = ImageStore::open(&path.to_string_lossy()).unwrap();
+    assert_eq!(reopened, store);
+    let image = reopened.find("https://example.com/a.png").unwrap();
+    assert_eq!(image.fotolife_url, "https://cdn/a2.png");
+    assert_eq!(image.documents, vec!["note.md", "other.md"]);
+  }
+}
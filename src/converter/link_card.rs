@@ -0,0 +1,182 @@
+use std::collections::HashMap;
+
+use pulldown_cmark::{Event, Tag};
+use reqwest::Url;
+
+use crate::util::escape_html;
+
+/// Marker to keep a standalone link as it is
+const NOEMBED_MARKER: &str = "<!-- noembed -->";
+
+/// Treatment of standalone links
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum LinkCardStyle {
+  /// Hatena's blog card, `[url:embed:cite]`
+  Embed,
+  /// Link with the title of the page, `[url:title]`
+  Title,
+  /// Link left as it is
+  Link,
+}
+
+impl LinkCardStyle {
+  pub fn from(name: &str) -> Option<Self> {
+    match name {
+      "embed" => Some(Self::Embed),
+      "title" => Some(Self::Title),
+      "link" => Some(Self::Link),
+      _ => None,
+    }
+  }
+
+  /// Decide style of a link from its host.
+  ///
+  /// Rules of a domain also apply to its subdomains, and the most specific rule is used.
+  ///
+  /// # Arguments
+  ///
+  /// * `url` - URL of the link
+  /// * `rules` - Mapping of host and style name
+  /// * `default` - Style for hosts matching no rules
+  pub fn of(url: &str, rules: &HashMap<String, String>, default: Self) -> Self {
+    let host = match Url::parse(url)
+      .ok()
+      .and_then(|url| url.host_str().map(String::from))
+    {
+      Some(host) => host,
+      None => return default,
+    };
+
+    rules
+      .iter()
+      .filter(|(domain, _)| host == **domain || host.ends_with(&format!(".{}", domain)))
+      .max_by_key(|(domain, _)| domain.len())
+      .and_then(|(_, style)| Self::from(style))
+      .unwrap_or(default)
+  }
+}
+
+/// Convert paragraphs consisting only of a URL or an autolink into Hatena's link notation.
+///
+/// Paragraphs with `<!-- noembed -->` are left as they are,
+/// as are paragraphs in footnote definitions.
+///
+/// # Arguments
+///
+/// * `events` - Events of whole document
+/// * `rules` - Mapping of host and style name
+/// * `default` - Style for hosts matching no rules
+pub fn convert_link_cards<'a>(
+  events: Vec<Event<'a>>,
+  rules: &HashMap<String, String>,
+  default: LinkCardStyle,
+) -> Vec<Event<'a>> {
+  let mut converted = vec![];
+  let mut paragraph: Option<Vec<Event>> = None;
+  let mut footnote_depth = 0;
+
+  for event in events {
+    match event {
+      Event::Start(Tag::FootnoteDefinition(_)) => {
+        footnote_depth += 1;
+        converted.push(event);
+      }
+      Event::End(Tag::FootnoteDefinition(_)) => {
+        footnote_depth -= 1;
+        converted.push(event);
+      }
+      Event::Start(Tag::Paragraph) if footnote_depth == 0 => {
+        paragraph = Some(vec![event]);
+      }
+      Event::End(Tag::Paragraph) if paragraph.is_some() => {
+        let mut events = paragraph.take().unwrap();
+        events.push(event);
+        let url = standalone_url(&events[1..events.len() - 1]);
+        match url.map(|url| (LinkCardStyle::of(&url, rules, default), url)) {
+          Some((LinkCardStyle::Embed, url)) => converted.push(Event::Html(
+            format!("<p>[{}:embed:cite]</p>\n", escape_html(&url)).into(),
+          )),
+          Some((LinkCardStyle::Title, url)) => converted.push(Event::Html(
+            format!("<p>[{}:title]</p>\n", escape_html(&url)).into(),
+          )),
+          _ => converted.extend(events),
+        }
+      }
+      event => match paragraph.as_mut() {
+        Some(paragraph) => paragraph.push(event),
+        None => converted.push(event),
+      },
+    }
+  }
+
+  converted
+}
+
+/// Returns URL if the content of paragraph is only a URL or a link whose text is its URL
+fn standalone_url(events: &[Event]) -> Option<String> {
+  let mut url: Option<String> = None;
+  let mut text = String::new();
+  let mut in_link = false;
+
+  for event in events {
+    match event {
+      Event::Html(html) if html.trim() == NOEMBED_MARKER => return None,
+      Event::Start(Tag::Link(_, dest, _)) if url.is_none() && text.trim().is_empty() => {
+        url = Some(dest.to_string());
+        in_link = true;
+      }
+      Event::End(Tag::Link(..)) if in_link => {
+        if text.trim() != url.as_deref().unwrap_or_default() {
+          return None;
+        }
+        in_link = false;
+        text.clear();
+      }
+      Event::Text(t) => text.push_str(t),
+      Event::SoftBreak => text.push(' '),
+      _ => return None,
+    }
+  }
+
+  let url = match url {
+    Some(url) if text.trim().is_empty() => url,
+    Some(_) => return None,
+    None => text.trim().to_string(),
+  };
+  let is_url = (url.starts_with("https://") || url.starts_with("http://"))
+    && !url.contains(char::is_whitespace);
+  is_url.then_some(url)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use pulldown_cmark::{html, Options, Parser};
+
+  #[test]
+  fn test_convert_link_cards() {
+    let markdown = "https://example.com/a\n\n<https://github.com/smallkirby>\n\n\
+      [https://twitter.com/x](https://twitter.com/x)\n\n\
+      <https://example.com/b> <!-- noembed -->\n\n\
+      See https://example.com/c\n\n[^1]\n\n[^1]: https://example.com/d\n\n\
+      https://example.com/e?a=1&b=2\n";
+    let mut rules = HashMap::new();
+    rules.insert("github.com".to_string(), "title".to_string());
+    rules.insert("twitter.com".to_string(), "link".to_string());
+
+    let events = Parser::new_ext(markdown, Options::all()).collect();
+    let events = convert_link_cards(events, &rules, LinkCardStyle::Embed);
+    let mut converted = String::new();
+    html::push_html(&mut converted, events.into_iter());
+
+    assert!(converted.starts_with(
+      "<p>[https://example.com/a:embed:cite]</p>\n\
+      <p>[https://github.com/smallkirby:title]</p>\n\
+      <p><a href=\"https://twitter.com/x\">https://twitter.com/x</a></p>\n\
+      <p><a href=\"https://example.com/b\">https://example.com/b</a> <!-- noembed --></p>\n\
+      <p>See https://example.com/c</p>\n"
+    ));
+    assert!(converted.contains("<p>https://example.com/d</p>"));
+    assert!(converted.contains("<p>[https://example.com/e?a=1&amp;b=2:embed:cite]</p>"));
+  }
+}
@@ -0,0 +1,290 @@
+pub mod mathml;
+pub mod tex;
+
+use super::{
+  fence::{code_span_len, FenceTracker},
+  placeholder::Placeholders,
+};
+use crate::util::escape_html;
+
+/// Renderer of math.
+///
+/// Errors are messages of TeX which cannot be rendered.
+pub trait Math {
+  fn inline_math(&self, tex: &str) -> Result<String, String>;
+  fn display_math(&self, tex: &str) -> Result<String, String>;
+  fn postdoc(&self) -> String;
+}
+
+impl dyn Math {
+  pub fn from(name: &str) -> Option<Box<dyn Math>> {
+    match name {
+      "tex" | "hatena" => Some(Box::new(tex::HatenaTex {})),
+      "mathml" => Some(Box::new(mathml::MathML {})),
+      _ => None,
+    }
+  }
+}
+
+/// Replace `$...$` and `$$...$$` math with placeholders of rendered math.
+///
+/// Math is extracted before markdown parsing so that emphasis in TeX is not interpreted.
+/// Fenced codeblocks and code spans are left untouched.
+/// A display math occupying whole lines is converted into block placeholder.
+/// Math which cannot be rendered is left as text, with a warning naming its line.
+///
+/// # Arguments
+///
+/// * `markdown` - HackMD note
+/// * `math` - Renderer of math
+/// * `placeholders` - Store to put rendered math
+/// * `warnings` - Warnings of math which cannot be rendered
+pub fn extract_math(
+  markdown: &str,
+  math: &dyn Math,
+  placeholders: &mut Placeholders,
+  warnings: &mut Vec<String>,
+) -> String {
+  let mut fence = FenceTracker::new();
+  let mut lines: Vec<String> = vec![];
+  // Line number and TeX of multi-line display math being read
+  let mut display: Option<(usize, String)> = None;
+
+  for (lineno, line) in markdown.lines().enumerate() {
+    let mut renderer = Renderer {
+      math,
+      placeholders: &mut *placeholders,
+      warnings: &mut *warnings,
+      lineno: lineno + 1,
+    };
+
+    if let Some((start, tex)) = display.as_mut() {
+      match line.find("$$") {
+        Some(end) => {
+          tex.push_str(&line[..end]);
+          renderer.lineno = *start;
+          lines.push(renderer.block(tex.trim()));
+          renderer.lineno = lineno + 1;
+          display = None;
+          let rest = &line[end + 2..];
+          if !rest.trim().is_empty() {
+            lines.push(renderer.replace_line(rest));
+          }
+        }
+        None => {
+          tex.push_str(line);
+          tex.push('\n');
+        }
+      }
+      continue;
+    }
+
+    if fence.feed(line) {
+      lines.push(line.to_string());
+      continue;
+    }
+
+    let trimmed = line.trim();
+    if let Some(rest) = trimmed.strip_prefix("$$") {
+      match rest.find("$$") {
+        // `$$...$$` occupying whole line
+        Some(end) if end + 2 == rest.len() => {
+          lines.push(renderer.block(rest[..end].trim()));
+          continue;
+        }
+        // Start of multi-line display math
+        None => {
+          display = Some((lineno + 1, format!("{}\n", rest)));
+          continue;
+        }
+        _ => {}
+      }
+    }
+
+    lines.push(renderer.replace_line(line));
+  }
+
+  // Unterminated display math is left as it is
+  if let Some((_, tex)) = display {
+    lines.push(format!("$${}", tex));
+  }
+
+  let mut replaced = lines.join("\n");
+  replaced.push('\n');
+  replaced
+}
+
+/// Renderer of math found in a line of markdown
+struct Renderer<'a> {
+  math: &'a dyn Math,
+  placeholders: &'a mut Placeholders,
+  warnings: &'a mut Vec<String>,
+  lineno: usize,
+}
+
+impl<'a> Renderer<'a> {
+  /// Render math, or returns math as text with a warning if it cannot be rendered
+  fn render(&mut self, tex: &str, display: bool) -> String {
+    let rendered = if display {
+      self.math.display_math(tex)
+    } else {
+      self.math.inline_math(tex)
+    };
+
+    match rendered {
+      Ok(html) => html,
+      Err(message) => {
+        self.warnings.push(format!(
+          "line {}: {} in math `{}`",
+          self.lineno, message, tex
+        ));
+        let delimiter = if display { "$$" } else { "$" };
+        format!(
+          r#"<code class="math-error">{}{}{}</code>"#,
+          delimiter,
+          escape_html(tex),
+          delimiter
+        )
+      }
+    }
+  }
+
+  /// Render display math into block placeholder
+  fn block(&mut self, tex: &str) -> String {
+    let html = self.render(tex, true);
+    self.placeholders.block(html)
+  }
+
+  /// Replace inline math in a line
+  fn replace_line(&mut self, line: &str) -> String {
+    let mut replaced = String::with_capacity(line.len());
+    let mut rest = line;
+
+    while let Some(pos) = rest.find(['`', '$', '\\']) {
+      replaced.push_str(&rest[..pos]);
+      rest = &rest[pos..];
+
+      if rest.starts_with('\\') {
+        // Escaped character is copied as it is
+        let len = 1 + rest[1..].chars().next().map(|c| c.len_utf8()).unwrap_or(0);
+        replaced.push_str(&rest[..len]);
+        rest = &rest[len..];
+      } else if rest.starts_with('`') {
+        // Code span is copied as it is
+        let len = code_span_len(rest);
+        replaced.push_str(&rest[..len]);
+        rest = &rest[len..];
+      } else if let Some(inner) = rest.strip_prefix("$$") {
+        match inner.find("$$") {
+          Some(end) if end > 0 => {
+            let html = self.render(inner[..end].trim(), true);
+            replaced.push_str(&self.placeholders.inline(html));
+            rest = &inner[end + 2..];
+          }
+          _ => {
+            replaced.push_str("$$");
+            rest = inner;
+          }
+        }
+      } else {
+        let inner = &rest[1..];
+        match inline_math_end(inner) {
+          Some(end) => {
+            let html = self.render(&inner[..end], false);
+            replaced.push_str(&self.placeholders.inline(html));
+            rest = &inner[end + 1..];
+          }
+          None => {
+            replaced.push('$');
+            rest = inner;
+          }
+        }
+      }
+    }
+    replaced.push_str(rest);
+
+    replaced
+  }
+}
+
+/// Find closing `$` of inline math.
+///
+/// Following Pandoc, the opening `$` must be followed by non-space,
+/// and the closing `$` must be preceded by non-space and not followed by a digit.
+/// So that `$5 and $10` is not considered as math.
+/// Inline math cannot contain code spans.
+fn inline_math_end(s: &str) -> Option<usize> {
+  let first = s.chars().next()?;
+  if first.is_whitespace() || first == '$' {
+    return None;
+  }
+
+  let mut prev = first;
+  let mut chars = s.char_indices().skip(1);
+  while let Some((i, c)) = chars.next() {
+    match c {
+      '\\' => {
+        chars.next();
+      }
+      '`' => return None,
+      '$' => {
+        let next = s[i + 1..].chars().next();
+        if !prev.is_whitespace() && !next.map(|c| c.is_ascii_digit()).unwrap_or(false) {
+          return Some(i);
+        }
+      }
+      _ => {}
+    }
+    prev = c;
+  }
+
+  None
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_extract_math() {
+    let math = <dyn Math>::from("tex").unwrap();
+    let mut placeholders = Placeholders::new();
+    let mut warnings = vec![];
+    let markdown = "costs $5 and $10, `$x$` and $a_1^2 *b*$\n$$\n[x]\n$$";
+    let replaced = extract_math(markdown, math.as_ref(), &mut placeholders, &mut warnings);
+
+    assert_eq!(
+      replaced,
+      "costs $5 and $10, `$x$` and <md2hatena-placeholder data-id=\"0\"></md2hatena-placeholder>\n\n<!-- md2hatena-placeholder 1 -->\n\n"
+    );
+    assert_eq!(
+      placeholders.resolve(r#"<md2hatena-placeholder data-id="0">"#),
+      Some(r"[tex:a\_1\^2 *b*]")
+    );
+    assert_eq!(
+      placeholders.resolve("<!-- md2hatena-placeholder 1 -->\n"),
+      Some(
+        r#"<div class="math-display" style="text-align: center;">[tex:\displaystyle [x\]]</div>"#
+      )
+    );
+    assert!(warnings.is_empty());
+  }
+
+  #[test]
+  fn test_warn_unsupported_math() {
+    let math = <dyn Math>::from("mathml").unwrap();
+    let mut placeholders = Placeholders::new();
+    let mut warnings = vec![];
+    extract_math(
+      "ok $x$\n\n$$\n\\unknown{x}\n$$",
+      math.as_ref(),
+      &mut placeholders,
+      &mut warnings,
+    );
+
+    assert_eq!(
+      warnings,
+      vec!["line 3: unsupported command `\\unknown` in math `\\unknown{x}`"]
+    );
+  }
+}
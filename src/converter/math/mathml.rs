@@ -0,0 +1,806 @@
+use super::Math;
+use crate::util::escape_html;
+
+/// Static MathML translated from TeX at conversion time, which needs no MathJax
+pub struct MathML {}
+
+impl Math for MathML {
+  fn inline_math(&self, tex: &str) -> Result<String, String> {
+    Ok(format!(
+      r#"<math xmlns="http://www.w3.org/1998/Math/MathML">{}</math>"#,
+      translate(tex, false)?
+    ))
+  }
+
+  fn display_math(&self, tex: &str) -> Result<String, String> {
+    Ok(format!(
+      r#"<math xmlns="http://www.w3.org/1998/Math/MathML" display="block">{}</math>"#,
+      translate(tex, true)?
+    ))
+  }
+
+  fn postdoc(&self) -> String {
+    "".into()
+  }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+  Command(String),
+  Char(char),
+  Space,
+  Open,
+  Close,
+  Sup,
+  Sub,
+  Amp,
+}
+
+const GREEKS: [(&str, &str); 48] = [
+  ("alpha", "α"),
+  ("beta", "β"),
+  ("gamma", "γ"),
+  ("delta", "δ"),
+  ("epsilon", "ϵ"),
+  ("varepsilon", "ε"),
+  ("zeta", "ζ"),
+  ("eta", "η"),
+  ("theta", "θ"),
+  ("vartheta", "ϑ"),
+  ("iota", "ι"),
+  ("kappa", "κ"),
+  ("lambda", "λ"),
+  ("mu", "μ"),
+  ("nu", "ν"),
+  ("xi", "ξ"),
+  ("pi", "π"),
+  ("varpi", "ϖ"),
+  ("rho", "ρ"),
+  ("varrho", "ϱ"),
+  ("sigma", "σ"),
+  ("varsigma", "ς"),
+  ("tau", "τ"),
+  ("upsilon", "υ"),
+  ("phi", "ϕ"),
+  ("varphi", "φ"),
+  ("chi", "χ"),
+  ("psi", "ψ"),
+  ("omega", "ω"),
+  ("Gamma", "Γ"),
+  ("Delta", "Δ"),
+  ("Theta", "Θ"),
+  ("Lambda", "Λ"),
+  ("Xi", "Ξ"),
+  ("Pi", "Π"),
+  ("Sigma", "Σ"),
+  ("Upsilon", "Υ"),
+  ("Phi", "Φ"),
+  ("Psi", "Ψ"),
+  ("Omega", "Ω"),
+  ("infty", "∞"),
+  ("partial", "∂"),
+  ("nabla", "∇"),
+  ("emptyset", "∅"),
+  ("hbar", "ℏ"),
+  ("ell", "ℓ"),
+  ("aleph", "ℵ"),
+  ("prime", "′"),
+];
+
+const OPERATORS: [(&str, &str); 69] = [
+  ("times", "×"),
+  ("cdot", "⋅"),
+  ("pm", "±"),
+  ("mp", "∓"),
+  ("div", "÷"),
+  ("ast", "∗"),
+  ("star", "⋆"),
+  ("circ", "∘"),
+  ("bullet", "∙"),
+  ("oplus", "⊕"),
+  ("otimes", "⊗"),
+  ("leq", "≤"),
+  ("le", "≤"),
+  ("geq", "≥"),
+  ("ge", "≥"),
+  ("neq", "≠"),
+  ("ne", "≠"),
+  ("ll", "≪"),
+  ("gg", "≫"),
+  ("approx", "≈"),
+  ("equiv", "≡"),
+  ("sim", "∼"),
+  ("simeq", "≃"),
+  ("cong", "≅"),
+  ("propto", "∝"),
+  ("in", "∈"),
+  ("notin", "∉"),
+  ("ni", "∋"),
+  ("subset", "⊂"),
+  ("subseteq", "⊆"),
+  ("supset", "⊃"),
+  ("supseteq", "⊇"),
+  ("cup", "∪"),
+  ("cap", "∩"),
+  ("setminus", "∖"),
+  ("forall", "∀"),
+  ("exists", "∃"),
+  ("neg", "¬"),
+  ("lnot", "¬"),
+  ("land", "∧"),
+  ("wedge", "∧"),
+  ("lor", "∨"),
+  ("vee", "∨"),
+  ("perp", "⊥"),
+  ("parallel", "∥"),
+  ("mid", "∣"),
+  ("angle", "∠"),
+  ("to", "→"),
+  ("rightarrow", "→"),
+  ("leftarrow", "←"),
+  ("gets", "←"),
+  ("leftrightarrow", "↔"),
+  ("Rightarrow", "⇒"),
+  ("Leftarrow", "⇐"),
+  ("Leftrightarrow", "⇔"),
+  ("implies", "⟹"),
+  ("iff", "⟺"),
+  ("mapsto", "↦"),
+  ("ldots", "…"),
+  ("dots", "…"),
+  ("cdots", "⋯"),
+  ("vdots", "⋮"),
+  ("ddots", "⋱"),
+  ("langle", "⟨"),
+  ("rangle", "⟩"),
+  ("lfloor", "⌊"),
+  ("rfloor", "⌋"),
+  ("lceil", "⌈"),
+  ("rceil", "⌉"),
+];
+
+const BIG_OPERATORS: [(&str, &str); 11] = [
+  ("sum", "∑"),
+  ("prod", "∏"),
+  ("coprod", "∐"),
+  ("int", "∫"),
+  ("iint", "∬"),
+  ("iiint", "∭"),
+  ("oint", "∮"),
+  ("bigcup", "⋃"),
+  ("bigcap", "⋂"),
+  ("bigoplus", "⨁"),
+  ("bigotimes", "⨂"),
+];
+
+const FUNCTIONS: [&str; 21] = [
+  "sin", "cos", "tan", "cot", "sec", "csc", "arcsin", "arccos", "arctan", "sinh", "cosh", "tanh",
+  "log", "ln", "lg", "exp", "arg", "deg", "dim", "ker", "hom",
+];
+
+/// Functions which take limits under them in display style
+const LIMIT_FUNCTIONS: [&str; 9] = [
+  "lim", "liminf", "limsup", "max", "min", "sup", "inf", "det", "gcd",
+];
+
+const ACCENTS: [(&str, &str); 10] = [
+  ("hat", "^"),
+  ("widehat", "^"),
+  ("bar", "¯"),
+  ("overline", "‾"),
+  ("vec", "→"),
+  ("overrightarrow", "→"),
+  ("tilde", "~"),
+  ("widetilde", "~"),
+  ("dot", "˙"),
+  ("ddot", "¨"),
+];
+
+const VARIANTS: [(&str, &str); 9] = [
+  ("mathbf", "bold"),
+  ("boldsymbol", "bold-italic"),
+  ("mathit", "italic"),
+  ("mathbb", "double-struck"),
+  ("mathcal", "script"),
+  ("mathscr", "script"),
+  ("mathfrak", "fraktur"),
+  ("mathsf", "sans-serif"),
+  ("mathtt", "monospace"),
+];
+
+const SPACES: [(&str, &str); 8] = [
+  (",", "0.1667em"),
+  (":", "0.2222em"),
+  (">", "0.2222em"),
+  (";", "0.2778em"),
+  (" ", "0.25em"),
+  ("quad", "1em"),
+  ("qquad", "2em"),
+  ("!", "-0.1667em"),
+];
+
+/// Commands which only change styles of the rest, and are ignored
+const IGNORED: [&str; 6] = [
+  "displaystyle",
+  "textstyle",
+  "scriptstyle",
+  "limits",
+  "nolimits",
+  "nonumber",
+];
+
+fn lookup<'a>(table: &[(&str, &'a str)], name: &str) -> Option<&'a str> {
+  table
+    .iter()
+    .find(|(command, _)| *command == name)
+    .map(|(_, value)| *value)
+}
+
+/// Translate TeX into MathML content of `<math>` element.
+///
+/// Returns an error describing unsupported commands or malformed TeX.
+///
+/// # Arguments
+///
+/// * `tex` - TeX of math
+/// * `display` - Whether the math is display style
+pub fn translate(tex: &str, display: bool) -> Result<String, String> {
+  let mut translator = Translator {
+    tokens: tokenize(tex),
+    pos: 0,
+    display,
+  };
+
+  let rows = translator.table()?;
+  match translator.next() {
+    None => {}
+    Some(Token::Close) => return Err("unbalanced braces".into()),
+    Some(Token::Command(name)) => return Err(format!("unexpected `\\{}`", name)),
+    Some(token) => return Err(format!("unexpected {:?}", token)),
+  }
+
+  if rows.len() == 1 && rows[0].len() == 1 {
+    Ok(rows[0][0].clone())
+  } else {
+    Ok(mtable(rows, &["right", "left"]))
+  }
+}
+
+fn tokenize(tex: &str) -> Vec<Token> {
+  let mut tokens = vec![];
+  let mut chars = tex.chars().peekable();
+
+  while let Some(c) = chars.next() {
+    let token = match c {
+      '\\' => match chars.next() {
+        Some(c) if c.is_ascii_alphabetic() => {
+          let mut name = c.to_string();
+          while let Some(&c) = chars.peek() {
+            if !c.is_ascii_alphabetic() {
+              break;
+            }
+            name.push(c);
+            chars.next();
+          }
+          Token::Command(name)
+        }
+        Some(c) => Token::Command(c.to_string()),
+        None => Token::Char('\\'),
+      },
+      '{' => Token::Open,
+      '}' => Token::Close,
+      '^' => Token::Sup,
+      '_' => Token::Sub,
+      '&' => Token::Amp,
+      '%' => {
+        // Comment until the end of line
+        for c in chars.by_ref() {
+          if c == '\n' {
+            break;
+          }
+        }
+        continue;
+      }
+      c if c.is_whitespace() => Token::Space,
+      c => Token::Char(c),
+    };
+    tokens.push(token);
+  }
+
+  tokens
+}
+
+struct Translator {
+  tokens: Vec<Token>,
+  pos: usize,
+  display: bool,
+}
+
+impl Translator {
+  fn skip_spaces(&mut self) {
+    while self.tokens.get(self.pos) == Some(&Token::Space) {
+      self.pos += 1;
+    }
+  }
+
+  fn peek(&mut self) -> Option<&Token> {
+    self.skip_spaces();
+    self.tokens.get(self.pos)
+  }
+
+  fn next(&mut self) -> Option<Token> {
+    self.skip_spaces();
+    let token = self.tokens.get(self.pos).cloned();
+    self.pos += 1;
+    token
+  }
+
+  fn peek_command(&mut self, names: &[&str]) -> bool {
+    matches!(self.peek(), Some(Token::Command(name)) if names.contains(&name.as_str()))
+  }
+
+  /// Parse rows separated by `\\` and cells separated by `&`
+  fn table(&mut self) -> Result<Vec<Vec<String>>, String> {
+    let mut rows = vec![];
+    let mut row = vec![];
+    loop {
+      let items = self.row()?;
+      row.push(mrow(items));
+      match self.peek() {
+        Some(Token::Amp) => {
+          self.pos += 1;
+        }
+        Some(Token::Command(name)) if name == "\\" || name == "cr" => {
+          self.pos += 1;
+          rows.push(std::mem::take(&mut row));
+        }
+        _ => {
+          rows.push(row);
+          break;
+        }
+      }
+    }
+
+    // Trailing `\\` does not make a new row
+    if rows.len() > 1
+      && rows
+        .last()
+        .unwrap()
+        .iter()
+        .all(|cell| cell == "<mrow></mrow>")
+    {
+      rows.pop();
+    }
+
+    Ok(rows)
+  }
+
+  /// Parse a sequence of items until the end of group, cell or row
+  fn row(&mut self) -> Result<Vec<String>, String> {
+    let mut items = vec![];
+    loop {
+      match self.peek() {
+        None | Some(Token::Close) | Some(Token::Amp) => break,
+        Some(Token::Command(name)) if ["\\", "cr", "right", "end"].contains(&name.as_str()) => {
+          break
+        }
+        _ => items.push(self.scripted()?),
+      }
+    }
+
+    Ok(items)
+  }
+
+  /// Parse an item with subscript and superscript
+  fn scripted(&mut self) -> Result<String, String> {
+    let (base, movable) = match self.peek() {
+      Some(Token::Sub) | Some(Token::Sup) => ("<mrow></mrow>".to_string(), false),
+      _ => self.atom(false)?,
+    };
+
+    let mut sub = None;
+    let mut sup = None;
+    loop {
+      match self.peek() {
+        Some(Token::Sub) if sub.is_none() => {
+          self.pos += 1;
+          sub = Some(self.argument()?);
+        }
+        Some(Token::Sup) if sup.is_none() => {
+          self.pos += 1;
+          sup = Some(self.argument()?);
+        }
+        Some(Token::Command(name)) if name == "limits" || name == "nolimits" => {
+          self.pos += 1;
+        }
+        _ => break,
+      }
+    }
+
+    let (sub_tag, sup_tag, subsup_tag) = if movable && self.display {
+      ("munder", "mover", "munderover")
+    } else {
+      ("msub", "msup", "msubsup")
+    };
+    Ok(match (sub, sup) {
+      (None, None) => base,
+      (Some(sub), None) => format!("<{0}>{1}{2}</{0}>", sub_tag, base, sub),
+      (None, Some(sup)) => format!("<{0}>{1}{2}</{0}>", sup_tag, base, sup),
+      (Some(sub), Some(sup)) => format!("<{0}>{1}{2}{3}</{0}>", subsup_tag, base, sub, sup),
+    })
+  }
+
+  /// Parse an argument of commands or scripts, which is a group or a single token
+  fn argument(&mut self) -> Result<String, String> {
+    match self.peek() {
+      None => Err("missing argument".into()),
+      _ => Ok(self.atom(true)?.0),
+    }
+  }
+
+  /// Parse an atom, and returns whether it takes limits under and over it
+  ///
+  /// # Arguments
+  ///
+  /// * `single` - Whether to read only a single character of numbers
+  fn atom(&mut self, single: bool) -> Result<(String, bool), String> {
+    match self.next() {
+      Some(Token::Open) => {
+        let items = self.row()?;
+        self.expect_close()?;
+        Ok((mrow(items), false))
+      }
+      Some(Token::Char(c)) if c.is_ascii_digit() || c == '.' => {
+        let mut number = c.to_string();
+        if !single {
+          while let Some(Token::Char(c)) = self.tokens.get(self.pos) {
+            if !c.is_ascii_digit() && *c != '.' {
+              break;
+            }
+            number.push(*c);
+            self.pos += 1;
+          }
+        }
+        Ok((format!("<mn>{}</mn>", number), false))
+      }
+      Some(Token::Char(c)) if c.is_alphabetic() => Ok((format!("<mi>{}</mi>", c), false)),
+      Some(Token::Char('\'')) => Ok(("<mo>′</mo>".into(), false)),
+      Some(Token::Char(c)) => Ok((mo(&c.to_string()), false)),
+      Some(Token::Command(name)) => self.command(&name),
+      Some(Token::Close) => Err("unbalanced braces".into()),
+      Some(Token::Amp) => Err("unexpected `&`".into()),
+      Some(Token::Sub) | Some(Token::Sup) => Err("double subscripts or superscripts".into()),
+      Some(Token::Space) | None => Err("missing argument".into()),
+    }
+  }
+
+  fn command(&mut self, name: &str) -> Result<(String, bool), String> {
+    if let Some(symbol) = lookup(&GREEKS, name) {
+      return Ok((format!("<mi>{}</mi>", symbol), false));
+    }
+    if let Some(symbol) = lookup(&OPERATORS, name) {
+      return Ok((mo(symbol), false));
+    }
+    if let Some(symbol) = lookup(&BIG_OPERATORS, name) {
+      return Ok((format!(r#"<mo largeop="true">{}</mo>"#, symbol), true));
+    }
+    if FUNCTIONS.contains(&name) {
+      return Ok((format!("<mi>{}</mi>", name), false));
+    }
+    if LIMIT_FUNCTIONS.contains(&name) {
+      return Ok((format!("<mi>{}</mi>", name), true));
+    }
+    if let Some(width) = lookup(&SPACES, name) {
+      return Ok((format!(r#"<mspace width="{}"/>"#, width), false));
+    }
+    if IGNORED.contains(&name) {
+      return Ok(("".into(), false));
+    }
+    if let Some(accent) = lookup(&ACCENTS, name) {
+      let base = self.argument()?;
+      return Ok((
+        format!(r#"<mover accent="true">{}{}</mover>"#, base, mo(accent)),
+        false,
+      ));
+    }
+    if let Some(variant) = lookup(&VARIANTS, name) {
+      let base = self.argument()?;
+      let base = base
+        .replace("<mi>", &format!(r#"<mi mathvariant="{}">"#, variant))
+        .replace("<mn>", &format!(r#"<mn mathvariant="{}">"#, variant));
+      return Ok((base, false));
+    }
+
+    let element = match name {
+      "{" | "}" | "#" | "%" | "&" | "$" | "_" => mo(name),
+      "lbrace" => mo("{"),
+      "rbrace" => mo("}"),
+      "vert" => mo("|"),
+      "Vert" | "|" => mo("‖"),
+      "bmod" => "<mo>mod</mo>".into(),
+      "frac" | "dfrac" | "tfrac" | "cfrac" => {
+        let numerator = self.argument()?;
+        let denominator = self.argument()?;
+        format!("<mfrac>{}{}</mfrac>", numerator, denominator)
+      }
+      "binom" => {
+        let n = self.argument()?;
+        let k = self.argument()?;
+        format!(
+          r#"<mrow><mo>(</mo><mfrac linethickness="0">{}{}</mfrac><mo>)</mo></mrow>"#,
+          n, k
+        )
+      }
+      "sqrt" => {
+        if self.peek() == Some(&Token::Char('[')) {
+          self.pos += 1;
+          let mut index = vec![];
+          while self.peek() != Some(&Token::Char(']')) {
+            if self.peek().is_none() {
+              return Err("unterminated index of `\\sqrt`".into());
+            }
+            index.push(self.scripted()?);
+          }
+          self.pos += 1;
+          let base = self.argument()?;
+          format!("<mroot>{}{}</mroot>", base, mrow(index))
+        } else {
+          format!("<msqrt>{}</msqrt>", self.argument()?)
+        }
+      }
+      "underline" => format!(
+        r#"<munder accentunder="true">{}<mo>_</mo></munder>"#,
+        self.argument()?
+      ),
+      "left" => {
+        let open = self.delimiter()?;
+        let items = self.row()?;
+        if !self.peek_command(&["right"]) {
+          return Err("`\\left` without `\\right`".into());
+        }
+        self.pos += 1;
+        let close = self.delimiter()?;
+        format!("<mrow>{}{}{}</mrow>", open, items.concat(), close)
+      }
+      "text" | "textrm" | "textit" | "textbf" | "mbox" => {
+        format!("<mtext>{}</mtext>", escape_html(&self.raw_group()?))
+      }
+      "mathrm" | "operatorname" => format!(
+        r#"<mi mathvariant="normal">{}</mi>"#,
+        escape_html(&self.raw_group()?)
+      ),
+      "pmod" => format!(
+        "<mrow><mo>(</mo><mi>mod</mi><mspace width=\"0.25em\"/>{}<mo>)</mo></mrow>",
+        self.argument()?
+      ),
+      "begin" => self.environment()?,
+      _ => return Err(format!("unsupported command `\\{}`", name)),
+    };
+
+    Ok((element, false))
+  }
+
+  /// Parse a delimiter of `\left` and `\right`
+  fn delimiter(&mut self) -> Result<String, String> {
+    let delimiter = match self.next() {
+      Some(Token::Char('.')) => return Ok("".into()),
+      Some(Token::Char(c)) => c.to_string(),
+      Some(Token::Command(name)) => match name.as_str() {
+        "{" | "lbrace" => "{".into(),
+        "}" | "rbrace" => "}".into(),
+        "|" | "Vert" => "‖".into(),
+        "vert" => "|".into(),
+        name => match lookup(&OPERATORS, name) {
+          Some(symbol) => symbol.into(),
+          None => return Err(format!("unsupported delimiter `\\{}`", name)),
+        },
+      },
+      _ => return Err("missing delimiter".into()),
+    };
+
+    Ok(format!(
+      r#"<mo fence="true" stretchy="true">{}</mo>"#,
+      escape_html(&delimiter)
+    ))
+  }
+
+  fn environment(&mut self) -> Result<String, String> {
+    let name = self.raw_group()?;
+    let (open, close, align): (&str, &str, &[&str]) = match name.as_str() {
+      "matrix" | "smallmatrix" => ("", "", &["center"]),
+      "pmatrix" => ("(", ")", &["center"]),
+      "bmatrix" => ("[", "]", &["center"]),
+      "Bmatrix" => ("{", "}", &["center"]),
+      "vmatrix" => ("|", "|", &["center"]),
+      "Vmatrix" => ("‖", "‖", &["center"]),
+      "cases" => ("{", "", &["left"]),
+      "aligned" | "align" | "align*" | "split" | "alignat" | "alignedat" => {
+        ("", "", &["right", "left"])
+      }
+      "gathered" | "gather" | "gather*" => ("", "", &["center"]),
+      "array" => {
+        // Column specification is not supported, but skipped
+        self.raw_group()?;
+        ("", "", &["center"])
+      }
+      _ => return Err(format!("unsupported environment `{}`", name)),
+    };
+
+    let rows = self.table()?;
+    if !self.peek_command(&["end"]) {
+      return Err(format!("unterminated environment `{}`", name));
+    }
+    self.pos += 1;
+    let end = self.raw_group()?;
+    if end != name {
+      return Err(format!("`{}` is closed by `{}`", name, end));
+    }
+
+    let fence = |delimiter: &str| {
+      if delimiter.is_empty() {
+        "".to_string()
+      } else {
+        format!(r#"<mo fence="true" stretchy="true">{}</mo>"#, delimiter)
+      }
+    };
+    Ok(format!(
+      "<mrow>{}{}{}</mrow>",
+      fence(open),
+      mtable(rows, align),
+      fence(close)
+    ))
+  }
+
+  /// Read a group as raw text
+  fn raw_group(&mut self) -> Result<String, String> {
+    if self.next() != Some(Token::Open) {
+      return Err("missing `{`".into());
+    }
+
+    let mut text = String::new();
+    let mut depth = 0;
+    loop {
+      let token = self.tokens.get(self.pos).cloned();
+      self.pos += 1;
+      match token {
+        None => return Err("unbalanced braces".into()),
+        Some(Token::Close) if depth == 0 => break,
+        Some(Token::Close) => {
+          depth -= 1;
+          text.push('}');
+        }
+        Some(Token::Open) => {
+          depth += 1;
+          text.push('{');
+        }
+        Some(Token::Command(name)) if name.len() == 1 => text.push_str(&name),
+        Some(Token::Command(name)) => {
+          text.push('\\');
+          text.push_str(&name);
+        }
+        Some(Token::Char(c)) => text.push(c),
+        Some(Token::Space) => text.push(' '),
+        Some(Token::Sup) => text.push('^'),
+        Some(Token::Sub) => text.push('_'),
+        Some(Token::Amp) => text.push('&'),
+      }
+    }
+
+    Ok(text)
+  }
+
+  fn expect_close(&mut self) -> Result<(), String> {
+    match self.next() {
+      Some(Token::Close) => Ok(()),
+      Some(Token::Command(name)) => Err(format!("unexpected `\\{}`", name)),
+      _ => Err("unbalanced braces".into()),
+    }
+  }
+}
+
+fn mo(symbol: &str) -> String {
+  format!("<mo>{}</mo>", escape_html(symbol))
+}
+
+fn mrow(items: Vec<String>) -> String {
+  if items.len() == 1 {
+    items.into_iter().next().unwrap()
+  } else {
+    format!("<mrow>{}</mrow>", items.concat())
+  }
+}
+
+/// Build a table, aligning columns by repeating `align`
+fn mtable(rows: Vec<Vec<String>>, align: &[&str]) -> String {
+  let columns = rows.iter().map(|row| row.len()).max().unwrap_or(0);
+  let columnalign = (0..columns)
+    .map(|i| align[i % align.len()])
+    .collect::<Vec<_>>()
+    .join(" ");
+  let rows = rows
+    .into_iter()
+    .map(|row| {
+      let cells = row
+        .into_iter()
+        .map(|cell| format!("<mtd>{}</mtd>", cell))
+        .collect::<String>();
+      format!("<mtr>{}</mtr>", cells)
+    })
+    .collect::<String>();
+
+  format!(r#"<mtable columnalign="{}">{}</mtable>"#, columnalign, rows)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_translate() {
+    assert_eq!(
+      translate(r"\frac{a_1}{2} + \sqrt{x^2}", false).unwrap(),
+      "<mrow><mfrac><msub><mi>a</mi><mn>1</mn></msub><mn>2</mn></mfrac><mo>+</mo><msqrt><msup><mi>x</mi><mn>2</mn></msup></msqrt></mrow>"
+    );
+    assert_eq!(
+      translate(r"\sum_{i=0}^n i", true).unwrap(),
+      "<mrow><munderover><mo largeop=\"true\">∑</mo><mrow><mi>i</mi><mo>=</mo><mn>0</mn></mrow><mi>n</mi></munderover><mi>i</mi></mrow>"
+    );
+    assert_eq!(
+      translate(r"\foo + 1", false).unwrap_err(),
+      "unsupported command `\\foo`"
+    );
+  }
+
+  #[test]
+  fn test_translate_cases() {
+    let cases = [
+      (r"\frac12", "<mfrac><mn>1</mn><mn>2</mn></mfrac>"),
+      (
+        r"\dfrac{x}{y+1}",
+        "<mfrac><mi>x</mi><mrow><mi>y</mi><mo>+</mo><mn>1</mn></mrow></mfrac>",
+      ),
+      (
+        r"x_i^2",
+        "<msubsup><mi>x</mi><mi>i</mi><mn>2</mn></msubsup>",
+      ),
+      (r"x^{10}", "<msup><mi>x</mi><mn>10</mn></msup>"),
+      (
+        r"x^10",
+        "<mrow><msup><mi>x</mi><mn>1</mn></msup><mn>0</mn></mrow>",
+      ),
+      (r"{}_n", "<msub><mrow></mrow><mi>n</mi></msub>"),
+      (r"\sqrt[3]{x}", "<mroot><mi>x</mi><mn>3</mn></mroot>"),
+      (
+        r"\begin{pmatrix} a & b \\ c & d \end{pmatrix}",
+        r#"<mrow><mo fence="true" stretchy="true">(</mo><mtable columnalign="center center"><mtr><mtd><mi>a</mi></mtd><mtd><mi>b</mi></mtd></mtr><mtr><mtd><mi>c</mi></mtd><mtd><mi>d</mi></mtd></mtr></mtable><mo fence="true" stretchy="true">)</mo></mrow>"#,
+      ),
+      (
+        r"\begin{cases} 1 & x > 0 \end{cases}",
+        r#"<mrow><mo fence="true" stretchy="true">{</mo><mtable columnalign="left left"><mtr><mtd><mn>1</mn></mtd><mtd><mrow><mi>x</mi><mo>&gt;</mo><mn>0</mn></mrow></mtd></mtr></mtable></mrow>"#,
+      ),
+      (r"\text{a < b}", "<mtext>a &lt; b</mtext>"),
+    ];
+    for (tex, mathml) in cases {
+      assert_eq!(translate(tex, false).unwrap(), mathml, "{}", tex);
+    }
+
+    let errors = [
+      (r"\unknown", "unsupported command `\\unknown`"),
+      (r"{x", "unbalanced braces"),
+      (r"x}", "unbalanced braces"),
+      (r"\frac{1}", "missing argument"),
+      (r"x^", "missing argument"),
+      (r"x_^2", "double subscripts or superscripts"),
+      (r"\left( x", "`\\left` without `\\right`"),
+      (r"\begin{foo} x \end{foo}", "unsupported environment `foo`"),
+      (
+        r"\begin{matrix} x \end{pmatrix}",
+        "`matrix` is closed by `pmatrix`",
+      ),
+      (r"\begin{matrix} x", "unterminated environment `matrix`"),
+    ];
+    for (tex, message) in errors {
+      assert_eq!(translate(tex, false).unwrap_err(), message, "{}", tex);
+    }
+  }
+}
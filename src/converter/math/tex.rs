@@ -0,0 +1,40 @@
+use super::Math;
+use crate::util::escape_html;
+
+/// Hatena's `[tex:...]` notation, rendered by MathJax on Hatena Blog
+pub struct HatenaTex {}
+
+impl Math for HatenaTex {
+  fn inline_math(&self, tex: &str) -> Result<String, String> {
+    Ok(format!("[tex:{}]", escape(tex)))
+  }
+
+  fn display_math(&self, tex: &str) -> Result<String, String> {
+    Ok(format!(
+      r#"<div class="math-display" style="text-align: center;">[tex:\displaystyle {}]</div>"#,
+      escape(tex)
+    ))
+  }
+
+  fn postdoc(&self) -> String {
+    "".into()
+  }
+}
+
+/// Escape TeX for Hatena notation.
+///
+/// Hatena terminates the notation at `]`, and treats `^` and `_` as markup,
+/// so they are escaped by backslash. The notation must also fit in a single line.
+fn escape(tex: &str) -> String {
+  let tex = tex
+    .lines()
+    .map(|line| line.trim())
+    .filter(|line| !line.is_empty())
+    .collect::<Vec<_>>()
+    .join(" ");
+
+  escape_html(&tex)
+    .replace(']', r"\]")
+    .replace('^', r"\^")
+    .replace('_', r"\_")
+}
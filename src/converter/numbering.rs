@@ -0,0 +1,258 @@
+/// Format of top-level numbers of headings
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum NumberFormat {
+  /// `1.`, `1.1`
+  Arabic,
+  /// `I.`, `I.1`
+  Roman,
+  /// `第1章`, `1.1`
+  Japanese,
+}
+
+impl NumberFormat {
+  pub fn from(name: &str) -> Option<Self> {
+    match name {
+      "arabic" => Some(Self::Arabic),
+      "roman" => Some(Self::Roman),
+      "japanese" | "ja" => Some(Self::Japanese),
+      _ => None,
+    }
+  }
+}
+
+/// Hierarchical numbering of headings
+pub struct HeadingNumbering {
+  /// Counters of each level, where index 0 is for H1
+  counters: Vec<usize>,
+  /// Deepest level to be numbered
+  depth: usize,
+  format: NumberFormat,
+}
+
+impl HeadingNumbering {
+  /// Create new numbering
+  ///
+  /// # Arguments
+  ///
+  /// * `depth` - Deepest level to be numbered. eg: If 2, H1 and H2 are numbered.
+  /// * `format` - Format of top-level numbers
+  pub fn new(depth: usize, format: NumberFormat) -> Self {
+    Self {
+      counters: vec![],
+      depth,
+      format,
+    }
+  }
+
+  /// Count up a heading, and returns its number prefix.
+  ///
+  /// Returns `None` if the heading is deeper than `depth` or already has a manual number.
+  /// Manually numbered headings still count up, so that following numbers are consistent.
+  ///
+  /// # Arguments
+  ///
+  /// * `level` - Level of heading in the note, starting from 1
+  /// * `text` - Text of heading
+  pub fn next(&mut self, level: usize, text: &str) -> Option<String> {
+    if level == 0 || level > self.depth {
+      return None;
+    }
+
+    self.counters.resize(level, 0);
+    // Skipped parent levels, such as H1 before the first H2, count as the first one
+    for counter in &mut self.counters[..level - 1] {
+      if *counter == 0 {
+        *counter = 1;
+      }
+    }
+    self.counters[level - 1] += 1;
+    if has_manual_number(text) {
+      return None;
+    }
+
+    let top = self.counters[0];
+    if level == 1 {
+      return Some(match self.format {
+        NumberFormat::Arabic => format!("{}. ", top),
+        NumberFormat::Roman => format!("{}. ", to_roman(top)),
+        NumberFormat::Japanese => format!("第{}章 ", top),
+      });
+    }
+
+    let top = match self.format {
+      NumberFormat::Roman => to_roman(top),
+      _ => top.to_string(),
+    };
+    let rest = self.counters[1..]
+      .iter()
+      .map(|counter| counter.to_string())
+      .collect::<Vec<_>>();
+    Some(format!("{}.{} ", top, rest.join(".")))
+  }
+}
+
+/// Check if heading text starts with a manual number,
+/// such as `1.`, `1.2`, `1)`, `IV.` or `第3章`.
+///
+/// Numbers must be followed by a space. Abbreviations such as `C. elegans`
+/// and versions such as `1.0 release` are not numbers.
+pub fn has_manual_number(text: &str) -> bool {
+  let text = text.trim_start();
+
+  // Japanese style
+  if let Some(rest) = text.strip_prefix('第') {
+    let digits = rest
+      .chars()
+      .take_while(|c| c.is_numeric() || "〇一二三四五六七八九十百".contains(*c))
+      .count();
+    let unit = rest.chars().nth(digits);
+    return digits > 0 && matches!(unit, Some('章' | '節' | '項' | '部'));
+  }
+
+  // Arabic or roman style, followed by a delimiter
+  let prefix_len = text
+    .find(|c: char| !(c.is_ascii_digit() || c == '.' || "IVXLC".contains(c)))
+    .unwrap_or(text.len());
+  let (prefix, rest) = text.split_at(prefix_len);
+  let (number, rest, delimited) = if let Some(number) = prefix.strip_suffix('.') {
+    (number, rest, true)
+  } else if let Some(rest) = rest.strip_prefix(')') {
+    (prefix, rest, true)
+  } else {
+    (prefix, rest, false)
+  };
+  if number.is_empty() || !(rest.is_empty() || rest.starts_with(char::is_whitespace)) {
+    return false;
+  }
+  // Title of a number starts with a capital, unlike `C. elegans` or `1.0 release notes`
+  if rest
+    .trim_start()
+    .starts_with(|c: char| c.is_ascii_lowercase())
+  {
+    return false;
+  }
+
+  let parts = number.split('.').collect::<Vec<_>>();
+  let is_arabic = parts
+    .iter()
+    .all(|part| !part.is_empty() && part.chars().all(|c| c.is_ascii_digit()));
+  if is_arabic {
+    // `1.2 ` is a number, but `2021 ` and `1.0 ` are not
+    return delimited || (parts.len() > 1 && parts.iter().all(|part| !part.starts_with('0')));
+  }
+  delimited && from_roman(number).map(to_roman).as_deref() == Some(number)
+}
+
+/// Parse a roman numeral of `I`, `V`, `X`, `L` and `C`, which may not be canonical
+fn from_roman(roman: &str) -> Option<usize> {
+  let values = roman
+    .chars()
+    .map(|c| match c {
+      'I' => Some(1),
+      'V' => Some(5),
+      'X' => Some(10),
+      'L' => Some(50),
+      'C' => Some(100),
+      _ => None,
+    })
+    .collect::<Option<Vec<usize>>>()?;
+  // Numerals smaller than one on their right are subtracted, such as `I` of `IV`
+  let mut num = 0usize;
+  let mut max = 0;
+  for value in values.into_iter().rev() {
+    if value < max {
+      num = num.checked_sub(value)?;
+    } else {
+      num += value;
+      max = value;
+    }
+  }
+  Some(num)
+}
+
+fn to_roman(mut num: usize) -> String {
+  const NUMERALS: [(usize, &str); 13] = [
+    (1000, "M"),
+    (900, "CM"),
+    (500, "D"),
+    (400, "CD"),
+    (100, "C"),
+    (90, "XC"),
+    (50, "L"),
+    (40, "XL"),
+    (10, "X"),
+    (9, "IX"),
+    (5, "V"),
+    (4, "IV"),
+    (1, "I"),
+  ];
+
+  let mut roman = String::new();
+  for (value, numeral) in NUMERALS {
+    while num >= value {
+      roman.push_str(numeral);
+      num -= value;
+    }
+  }
+  roman
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_hierarchical_numbering() {
+    let mut numbering = HeadingNumbering::new(2, NumberFormat::Roman);
+    let numbers = [
+      (1, "Intro"),
+      (2, "Background"),
+      (2, "2. Manual"),
+      (3, "Too deep"),
+      (2, "Motivation"),
+      (1, "Design"),
+      (2, "Overview"),
+    ]
+    .iter()
+    .map(|(level, text)| numbering.next(*level, text))
+    .collect::<Vec<_>>();
+
+    assert_eq!(
+      numbers,
+      vec![
+        Some("I. ".into()),
+        Some("I.1 ".into()),
+        None,
+        None,
+        Some("I.3 ".into()),
+        Some("II. ".into()),
+        Some("II.1 ".into()),
+      ]
+    );
+  }
+
+  #[test]
+  fn test_numbering_without_parent() {
+    let mut numbering = HeadingNumbering::new(3, NumberFormat::Arabic);
+    assert_eq!(numbering.next(2, "Summary"), Some("1.1 ".into()));
+    assert_eq!(numbering.next(3, "Detail"), Some("1.1.1 ".into()));
+    assert_eq!(numbering.next(1, "Intro"), Some("2. ".into()));
+    assert_eq!(numbering.next(3, "Skipped H2"), Some("2.1.1 ".into()));
+  }
+
+  #[test]
+  fn test_manual_number() {
+    assert!(has_manual_number("1. Intro"));
+    assert!(has_manual_number("1.2 Intro"));
+    assert!(has_manual_number("3) Intro"));
+    assert!(has_manual_number("IV. Intro"));
+    assert!(has_manual_number("第3章 はじめに"));
+    assert!(!has_manual_number("2021 in review"));
+    assert!(!has_manual_number("Intro"));
+    assert!(!has_manual_number("CLI usage"));
+    assert!(!has_manual_number("C. elegans overview"));
+    assert!(!has_manual_number("V. cholerae"));
+    assert!(!has_manual_number("1.0 release notes"));
+    assert!(!has_manual_number("IIV. Intro"));
+  }
+}
@@ -0,0 +1,69 @@
+const INLINE_OPEN: &str = "<md2hatena-placeholder data-id=\"";
+const INLINE_CLOSE: &str = "</md2hatena-placeholder>";
+const BLOCK_OPEN: &str = "<!-- md2hatena-placeholder ";
+const BLOCK_CLOSE: &str = " -->";
+
+/// Store of HTML fragments substituted for placeholders in markdown.
+///
+/// Preprocessors replace markdown which pulldown-cmark cannot handle with placeholders,
+/// then `Converter` puts the stored fragments back when it meets them as `Event::Html`.
+/// Inline placeholders are pairs of custom tags, which never start HTML blocks.
+/// Block placeholders are HTML comments put on their own lines.
+#[derive(Debug, Default)]
+pub struct Placeholders {
+  fragments: Vec<String>,
+}
+
+impl Placeholders {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  /// Store HTML fragment and returns inline placeholder for it
+  pub fn inline(&mut self, html: String) -> String {
+    self.fragments.push(html);
+    format!(
+      "{}{}\">{}",
+      INLINE_OPEN,
+      self.fragments.len() - 1,
+      INLINE_CLOSE
+    )
+  }
+
+  /// Store HTML fragment and returns block placeholder for it, surrounded by blank lines
+  pub fn block(&mut self, html: String) -> String {
+    self.fragments.push(html);
+    format!(
+      "\n{}{}{}\n",
+      BLOCK_OPEN,
+      self.fragments.len() - 1,
+      BLOCK_CLOSE
+    )
+  }
+
+  /// Get HTML fragment for raw HTML emitted by pulldown-cmark.
+  ///
+  /// Returns `None` if `html` is not a placeholder.
+  /// Closing tags of inline placeholders are resolved to empty string.
+  pub fn resolve(&self, html: &str) -> Option<&str> {
+    let html = html.trim();
+    if html == INLINE_CLOSE {
+      return Some("");
+    }
+
+    let id = if let Some(rest) = html.strip_prefix(INLINE_OPEN) {
+      rest.strip_suffix("\">")?
+    } else if let Some(rest) = html.strip_prefix(BLOCK_OPEN) {
+      rest.strip_suffix(BLOCK_CLOSE)?
+    } else {
+      return None;
+    };
+
+    let id = id.parse::<usize>().ok()?;
+    self.fragments.get(id).map(|fragment| fragment.as_str())
+  }
+
+  pub fn clear(&mut self) {
+    self.fragments.clear();
+  }
+}
@@ -0,0 +1,127 @@
+use super::fence::FenceTracker;
+use crate::util::escape_html;
+
+const TOC_MARKER: &str = "<!-- md2hatena-toc -->";
+
+/// Style of table of contents
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum TocStyle {
+  /// Hatena's `[:contents]` notation
+  Hatena,
+  /// Nested `<ul>` of links to headings
+  Html,
+}
+
+impl TocStyle {
+  pub fn from(name: &str) -> Option<Self> {
+    match name {
+      "hatena" => Some(Self::Hatena),
+      "html" => Some(Self::Html),
+      _ => None,
+    }
+  }
+}
+
+/// Entry of table of contents
+pub struct TocEntry<'a> {
+  /// Level of heading after adjusted by `heading_min`
+  pub level: usize,
+  pub text: &'a str,
+  pub id: &'a str,
+}
+
+/// Replace `[TOC]` lines with markers of table of contents
+pub fn mark_toc(markdown: &str) -> String {
+  let mut fence = FenceTracker::new();
+  let mut lines = vec![];
+
+  for line in markdown.lines() {
+    if !fence.feed(line) && line.trim().eq_ignore_ascii_case("[toc]") {
+      lines.push(format!("\n{}\n", TOC_MARKER));
+    } else {
+      lines.push(line.to_string());
+    }
+  }
+
+  let mut marked = lines.join("\n");
+  marked.push('\n');
+  marked
+}
+
+/// Check if raw HTML emitted by pulldown-cmark is a marker of table of contents
+pub fn is_toc_marker(html: &str) -> bool {
+  html.trim() == TOC_MARKER
+}
+
+/// Render table of contents
+pub fn render_toc(style: TocStyle, entries: &[TocEntry]) -> String {
+  match style {
+    TocStyle::Hatena => "<p>[:contents]</p>\n".into(),
+    TocStyle::Html => render_list(entries),
+  }
+}
+
+fn render_list(entries: &[TocEntry]) -> String {
+  let mut html = String::new();
+  // Levels of open `<ul>`
+  let mut stack: Vec<usize> = vec![];
+
+  for entry in entries {
+    match stack.last() {
+      None => {
+        html.push_str(r#"<ul class="table-of-contents">"#);
+        stack.push(entry.level);
+      }
+      Some(&top) if entry.level > top => {
+        html.push_str("<ul>");
+        stack.push(entry.level);
+      }
+      Some(_) => {
+        while stack.len() > 1 && entry.level < *stack.last().unwrap() {
+          html.push_str("</li></ul>");
+          stack.pop();
+        }
+        html.push_str("</li>");
+      }
+    }
+    html.push_str(&format!(
+      r##"<li><a href="#{}">{}</a>"##,
+      escape_html(entry.id),
+      escape_html(entry.text)
+    ));
+  }
+  for _ in stack {
+    html.push_str("</li></ul>");
+  }
+
+  html.push('\n');
+  html
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_render_list() {
+    let entries = [
+      (3, "A", "a"),
+      (4, "A-1", "a-1"),
+      (5, "A-1-1", "a-1-1"),
+      (3, "B", "b"),
+    ]
+    .iter()
+    .map(|&(level, text, id)| TocEntry { level, text, id })
+    .collect::<Vec<_>>();
+
+    assert_eq!(
+      render_toc(TocStyle::Html, &entries),
+      concat!(
+        r##"<ul class="table-of-contents"><li><a href="#a">A</a>"##,
+        r##"<ul><li><a href="#a-1">A-1</a><ul><li><a href="#a-1-1">A-1-1</a></li></ul></li></ul></li>"##,
+        r##"<li><a href="#b">B</a></li></ul>"##,
+        "\n"
+      )
+    );
+  }
+}
@@ -0,0 +1,233 @@
+use std::{
+  collections::VecDeque,
+  path::{Path, PathBuf},
+  sync::{
+    atomic::{AtomicUsize, Ordering},
+    Mutex,
+  },
+  thread,
+  time::Duration,
+};
+
+use bytes::Bytes;
+use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
+
+use crate::hackmd::error::HackMDError;
+
+/// Image failed to be downloaded
+#[derive(Debug)]
+pub struct DownloadFailure {
+  pub url: String,
+  pub error: HackMDError,
+}
+
+/// Downloader of images running a bounded number of workers
+pub struct Downloader {
+  /// Number of workers
+  parallelism: usize,
+  /// Number of retries after the first attempt
+  retries: u32,
+  /// Wait before the first retry, which doubles on every retry
+  backoff: Duration,
+}
+
+impl Downloader {
+  /// Create new Downloader
+  ///
+  /// # Arguments
+  ///
+  /// * `parallelism` - Number of concurrent downloads
+  /// * `retries` - Number of retries for transient errors
+  /// * `backoff` - Wait before the first retry
+  pub fn new(parallelism: usize, retries: u32, backoff: Duration) -> Self {
+    Self {
+      parallelism: parallelism.max(1),
+      retries,
+      backoff,
+    }
+  }
+
+  /// Download images and save them, then returns images failed to be downloaded.
+  ///
+  /// Directories to save images are created if missing,
+  /// and failures to write images are also returned instead of panicking.
+  ///
+  /// Each worker has its own progress bar under `progress`.
+  ///
+  /// # Arguments
+  ///
+  /// * `images` - URLs of images and paths to save them
+  /// * `fetch` - Function to fetch an image
+  /// * `progress` - Progress bars to add workers' bars
+  pub fn download<F>(
+    &self,
+    images: Vec<(String, PathBuf)>,
+    fetch: F,
+    progress: &MultiProgress,
+  ) -> Vec<DownloadFailure>
+  where
+    F: Fn(&str) -> Result<Bytes, HackMDError> + Sync,
+  {
+    let total = ProgressBar::new(images.len() as u64);
+    total.set_style(
+      ProgressStyle::with_template(
+        "  {spinner:.green} [{pos}/{len}] [{elapsed_precise}] [{bar:40.cyan/blue}] {msg}",
+      )
+      .unwrap()
+      .progress_chars("#>-"),
+    );
+    let total = progress.add(total);
+    let workers = self.parallelism.min(images.len());
+    let queue = Mutex::new(images.into_iter().collect::<VecDeque<_>>());
+    let failures = Mutex::new(vec![]);
+
+    thread::scope(|scope| {
+      for _ in 0..workers {
+        let bar = progress.add(ProgressBar::new_spinner());
+        bar.set_style(ProgressStyle::with_template("    {spinner:.blue} {msg}").unwrap());
+        let (queue, failures, fetch, total) = (&queue, &failures, &fetch, &total);
+        scope.spawn(move || {
+          loop {
+            // Lock is released before downloading
+            let image = queue.lock().unwrap().pop_front();
+            let (url, path) = match image {
+              Some(image) => image,
+              None => break,
+            };
+            bar.set_message(url.clone());
+            let result = self
+              .fetch_with_retry(&url, fetch, &bar)
+              .and_then(|bytes| Ok(save(&path, &bytes)?));
+            if let Err(error) = result {
+              failures
+                .lock()
+                .unwrap()
+                .push(DownloadFailure { url, error });
+            }
+            total.inc(1);
+          }
+          bar.finish_and_clear();
+        });
+      }
+    });
+
+    total.finish_with_message("Done");
+    failures.into_inner().unwrap()
+  }
+
+  /// Fetch an image, retrying transient errors with exponential backoff
+  fn fetch_with_retry<F>(
+    &self,
+    url: &str,
+    fetch: &F,
+    bar: &ProgressBar,
+  ) -> Result<Bytes, HackMDError>
+  where
+    F: Fn(&str) -> Result<Bytes, HackMDError>,
+  {
+    let mut wait = self.backoff;
+    let mut attempt = 0;
+    loop {
+      match fetch(url) {
+        Ok(bytes) => return Ok(bytes),
+        Err(error) if attempt < self.retries && is_transient(&error) => {
+          attempt += 1;
+          bar.set_message(format!("{} (retry {}/{})", url, attempt, self.retries));
+          thread::sleep(wait);
+          wait *= 2;
+        }
+        Err(error) => return Err(error),
+      }
+    }
+  }
+}
+
+/// Write downloaded image, creating its directory if missing.
+///
+/// The image is written into a temporary file in the same directory, then renamed,
+/// so that an interrupted write never leaves a truncated image to be reused as downloaded.
+fn save(path: &Path, bytes: &[u8]) -> Result<(), std::io::Error> {
+  static TEMP_ID: AtomicUsize = AtomicUsize::new(0);
+
+  if let Some(dir) = path.parent() {
+    std::fs::create_dir_all(dir)?;
+  }
+  // Workers downloading the same URL write into different temporary files
+  let mut temp_name = path.file_name().unwrap_or_default().to_os_string();
+  temp_name.push(format!(
+    ".{}-{}.tmp",
+    std::process::id(),
+    TEMP_ID.fetch_add(1, Ordering::Relaxed)
+  ));
+  let temp_path = path.with_file_name(temp_name);
+  let result = std::fs::write(&temp_path, bytes).and_then(|_| std::fs::rename(&temp_path, path));
+  if result.is_err() {
+    let _ = std::fs::remove_file(&temp_path);
+  }
+  result
+}
+
+/// Whether the error may be resolved by retrying, such as timeouts and server errors
+fn is_transient(error: &HackMDError) -> bool {
+  match error {
+    HackMDError::RequestFailure(e) => crate::util::is_transient_request(e),
+    _ => false,
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_download_with_retries() {
+    // Directory is created by the downloader
+    let dir = std::env::temp_dir().join("md2hatena-test-download");
+    let _ = std::fs::remove_dir_all(&dir);
+    let mut images = ["flaky", "ok1", "ok2", "broken"]
+      .iter()
+      .map(|name| (name.to_string(), dir.join(name)))
+      .collect::<Vec<_>>();
+    // Parent of the path is a file, so the image cannot be written
+    let file = std::env::temp_dir().join("md2hatena-test-download-file");
+    std::fs::write(&file, b"").unwrap();
+    images.push(("unwritable".into(), file.join("unwritable")));
+
+    // Port which refuses connections
+    let refused = {
+      let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+      format!("http://{}", listener.local_addr().unwrap())
+    };
+    let flaky_attempts = AtomicUsize::new(0);
+    let fetch = |url: &str| match url {
+      "flaky" if flaky_attempts.fetch_add(1, Ordering::SeqCst) < 2 => {
+        Err(reqwest::blocking::get(&refused).unwrap_err().into())
+      }
+      "broken" => Err(HackMDError::AuthentiocationFailure {
+        message: "denied".into(),
+      }),
+      url => Ok(Bytes::from(url.to_string())),
+    };
+
+    let downloader = Downloader::new(3, 2, Duration::from_millis(1));
+    let progress = MultiProgress::with_draw_target(indicatif::ProgressDrawTarget::hidden());
+    let failures = downloader.download(images, fetch, &progress);
+
+    assert_eq!(flaky_attempts.load(Ordering::SeqCst), 3);
+    assert_eq!(std::fs::read(dir.join("flaky")).unwrap(), b"flaky");
+    assert_eq!(std::fs::read(dir.join("ok2")).unwrap(), b"ok2");
+    let mut failed = failures
+      .iter()
+      .map(|failure| failure.url.as_str())
+      .collect::<Vec<_>>();
+    failed.sort();
+    assert_eq!(failed, ["broken", "unwritable"]);
+    // No temporary files are left
+    let mut files = std::fs::read_dir(&dir)
+      .unwrap()
+      .map(|entry| entry.unwrap().file_name().to_string_lossy().to_string())
+      .collect::<Vec<_>>();
+    files.sort();
+    assert_eq!(files, ["flaky", "ok1", "ok2"]);
+  }
+}
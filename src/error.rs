@@ -1,7 +1,7 @@
 use hatena_rs::oauth::error::OauthError;
 use thiserror::Error;
 
-use crate::hackmd::error::HackMDError;
+use crate::{hackmd::error::HackMDError, hatena::error::PublishError};
 
 #[derive(Debug, Error)]
 pub enum ApplicationError {
@@ -20,6 +20,15 @@ pub enum ApplicationError {
   #[error("Config parse failure")]
   ConfigParseFailure(#[from] serde_yaml::Error),
 
+  #[error("Image processing failure")]
+  ImageProcessFailure(#[from] image::ImageError),
+
+  #[error("Image store failure")]
+  ImageStoreFailure(#[from] serde_json::Error),
+
+  #[error("Publish failure")]
+  PublishFailure(#[from] PublishError),
+
   #[error("Misc error: {message:?}")]
   MiscError { message: String },
 }
@@ -1,6 +1,9 @@
 pub mod consts;
 pub mod cookie;
 pub mod error;
+pub mod note;
+
+use std::time::Duration;
 
 use bytes::Bytes;
 use reqwest::{
@@ -8,14 +11,24 @@ use reqwest::{
   header::{AUTHORIZATION, COOKIE, USER_AGENT},
   StatusCode,
 };
-use serde::{Deserialize, Serialize};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use self::{
+  consts::cookie::ENV_HACKMD_COOKIE,
+  cookie::HackMDCookie,
+  error::HackMDError,
+  note::{Note, NoteRef},
+};
 
-use self::{consts::cookie::ENV_HACKMD_COOKIE, cookie::HackMDCookie, error::HackMDError};
+/// Endpoint of HackMD API
+const DEFAULT_API_ENDPOINT: &str = "https://api.hackmd.io/v1";
 
 /// HackMD client
 pub struct HackMD {
-  api_token: String,    // API token to access HackMD
-  cookie: HackMDCookie, // Cookie manager
+  api_token: String,         // API token to access HackMD
+  cookie: HackMDCookie,      // Cookie manager
+  timeout: Option<Duration>, // Timeout of each request
+  api_endpoint: String,      // Endpoint of HackMD API
 }
 
 /// User information
@@ -54,23 +67,95 @@ impl HackMD {
       Some(cookie_env)
     });
 
-    Self { api_token, cookie }
+    Self {
+      api_token,
+      cookie,
+      timeout: None,
+      api_endpoint: DEFAULT_API_ENDPOINT.into(),
+    }
+  }
+
+  /// Set endpoint of HackMD API, such as `http://localhost:8080/v1`
+  pub fn set_api_endpoint(&mut self, api_endpoint: &str) {
+    self.api_endpoint = api_endpoint.trim_end_matches('/').into();
+  }
+
+  /// Set timeout of each request to HackMD API or to fetch images
+  ///
+  /// # Arguments
+  ///
+  /// * `timeout` - Timeout in seconds
+  pub fn set_timeout(&mut self, timeout: u64) {
+    self.timeout = Some(Duration::from_secs(timeout));
+  }
+
+  /// Client with the timeout of each request
+  fn client(&self) -> Result<Client, HackMDError> {
+    let mut builder = Client::builder();
+    if let Some(timeout) = self.timeout {
+      builder = builder.timeout(timeout);
+    }
+    Ok(builder.build()?)
   }
 
   /// Get user information of me
   pub fn me(&self) -> Result<UserInfo, HackMDError> {
-    let client = Client::new();
+    self.get_api("/me")
+  }
+
+  /// Get a note with its markdown
+  ///
+  /// Notes under a user or team are looked up by their short ID or permalink
+  /// if they are not found by note ID. Notes of a user are only looked up in my own notes,
+  /// because other users' notes are not listed by the API.
+  ///
+  /// # Arguments
+  ///
+  /// * `note` - Reference to the note
+  pub fn get_note(&self, note: &NoteRef) -> Result<Note, HackMDError> {
+    let (owner, key) = match note {
+      NoteRef::Id(id) => return self.get_api(&format!("/notes/{}", id)),
+      NoteRef::Path { owner, note } => (owner, note),
+    };
+    match self.get_api::<Note>(&format!("/notes/{}", key)) {
+      Err(e) if is_not_found(&e) => {}
+      result => return result,
+    }
+
+    let not_found = || HackMDError::NoteNotFound {
+      note: format!("@{}/{}", owner, key),
+    };
+    // Owner is either a team or me
+    let notes = match self.get_api::<Vec<Note>>(&format!("/teams/{}/notes", owner)) {
+      Err(e) if is_not_found(&e) => {
+        if self.me()?.user_path != *owner {
+          return Err(not_found());
+        }
+        self.get_api::<Vec<Note>>("/notes")?
+      }
+      result => result?,
+    };
+    match notes.iter().find(|note| note.is_referred_by(key)) {
+      Some(note) => self.get_api(&format!("/notes/{}", note.id)),
+      None => Err(not_found()),
+    }
+  }
+
+  /// Send GET request to HackMD API, and parse the response
+  ///
+  /// # Arguments
+  ///
+  /// * `path` - Path of API, such as `/me`
+  fn get_api<T: DeserializeOwned>(&self, path: &str) -> Result<T, HackMDError> {
+    let client = self.client()?;
     let res = client
-      .get("https://api.hackmd.io/v1/me")
+      .get(format!("{}{}", self.api_endpoint, path))
       .header(USER_AGENT, "hackmd-rs")
       .header(AUTHORIZATION, format!("Bearer {}", self.api_token))
       .send()?;
 
     match res.status() {
-      StatusCode::OK => {
-        let me: UserInfo = serde_json::from_str(&res.text()?)?;
-        Ok(me)
-      }
+      StatusCode::OK => Ok(serde_json::from_str(&res.text()?)?),
       StatusCode::UNAUTHORIZED => Err(HackMDError::AuthentiocationFailure {
         message: "API token is invalid?".into(),
       }),
@@ -100,7 +185,7 @@ impl HackMD {
   }
 
   fn get_normal_photo(&self, photo_url: &str) -> Result<Bytes, HackMDError> {
-    let client = Client::new();
+    let client = self.client()?;
     let res = client
       .get(photo_url)
       .header(USER_AGENT, "hackmd-rs")
@@ -121,7 +206,7 @@ impl HackMD {
   /// * `photo_name` - URL of protected image
   fn get_protected_photo(&self, photo_name: &str) -> Result<Bytes, HackMDError> {
     let cookie = self.cookie.get_cookie(false)?;
-    let client = Client::new();
+    let client = self.client()?;
     let res = client
       .get(format!("https://hackmd.io/_uploads/{}", photo_name))
       .header(USER_AGENT, "hackmd-rs")
@@ -140,3 +225,108 @@ impl HackMD {
     }
   }
 }
+
+/// Whether the request failed because the resource does not exist
+fn is_not_found(error: &HackMDError) -> bool {
+  matches!(error, HackMDError::RequestFailure(e) if e.status() == Some(StatusCode::NOT_FOUND))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::{
+    io::{BufRead, BufReader, Write},
+    net::TcpListener,
+  };
+
+  /// Serve `count` requests with responses for their paths, and returns requested paths
+  fn serve(
+    count: usize,
+    respond: fn(&str) -> (&'static str, &'static str),
+  ) -> (String, std::thread::JoinHandle<Vec<String>>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let endpoint = format!("http://{}/v1", listener.local_addr().unwrap());
+    let server = std::thread::spawn(move || {
+      let mut paths = vec![];
+      for stream in listener.incoming().take(count) {
+        let mut stream = stream.unwrap();
+        let mut request_line = String::new();
+        BufReader::new(&stream)
+          .read_line(&mut request_line)
+          .unwrap();
+        let path = request_line.split(' ').nth(1).unwrap().to_string();
+        let (status, body) = respond(&path);
+        write!(
+          stream,
+          "HTTP/1.1 {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+          status,
+          body.len(),
+          body
+        )
+        .unwrap();
+        paths.push(path);
+      }
+      paths
+    });
+    (endpoint, server)
+  }
+
+  #[test]
+  fn test_get_note_by_permalink() {
+    let (endpoint, server) = serve(3, |path| match path {
+      "/v1/teams/team/notes" => (
+        "200 OK",
+        r#"[{"id":"other","title":"Other"},{"id":"abc","title":"Hello","permalink":"xyz"}]"#,
+      ),
+      "/v1/notes/abc" => (
+        "200 OK",
+        r##"{"id":"abc","title":"Hello","content":"# Hello\n","permalink":"xyz"}"##,
+      ),
+      _ => ("404 Not Found", "{}"),
+    });
+
+    let mut hackmd = HackMD::new("token".into());
+    hackmd.set_api_endpoint(&endpoint);
+    let note = hackmd
+      .get_note(&NoteRef::parse("https://hackmd.io/@team/xyz").unwrap())
+      .unwrap();
+    let paths = server.join().unwrap();
+
+    assert_eq!(
+      paths,
+      vec!["/v1/notes/xyz", "/v1/teams/team/notes", "/v1/notes/abc"]
+    );
+    assert_eq!(note.id, "abc");
+    assert_eq!(note.content, "# Hello\n");
+  }
+
+  #[test]
+  fn test_get_note_errors() {
+    // Invalid token is not reported as missing note
+    let (endpoint, server) = serve(1, |_| ("401 Unauthorized", "{}"));
+    let mut hackmd = HackMD::new("token".into());
+    hackmd.set_api_endpoint(&endpoint);
+    let result = hackmd.get_note(&NoteRef::parse("https://hackmd.io/@team/xyz").unwrap());
+    assert!(matches!(
+      result,
+      Err(HackMDError::AuthentiocationFailure { .. })
+    ));
+    assert_eq!(server.join().unwrap(), vec!["/v1/notes/xyz"]);
+
+    // Notes of other users are not looked up in my notes
+    let (endpoint, server) = serve(3, |path| match path {
+      "/v1/me" => (
+        "200 OK",
+        r#"{"id":"1","name":"Me","email":"me@example.com","userPath":"me","photo":"","teams":[]}"#,
+      ),
+      _ => ("404 Not Found", "{}"),
+    });
+    hackmd.set_api_endpoint(&endpoint);
+    let result = hackmd.get_note(&NoteRef::parse("https://hackmd.io/@otheruser/xyz").unwrap());
+    assert!(matches!(result, Err(HackMDError::NoteNotFound { .. })));
+    assert_eq!(
+      server.join().unwrap(),
+      vec!["/v1/notes/xyz", "/v1/teams/otheruser/notes", "/v1/me"]
+    );
+  }
+}
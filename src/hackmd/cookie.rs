@@ -1,12 +1,16 @@
-use std::io::{self, Write};
+use std::{
+  io::{self, Write},
+  sync::Mutex,
+};
 
 use crate::hackmd::error::HackMDError;
 
 /// HackMD client to get a logged-in cookie.
 ///
 /// This struct needs user interaction to retrieve cookie.
+/// The cookie input by user is cached, so that concurrent downloads ask it only once.
 pub struct HackMDCookie {
-  cookie: Option<String>,
+  cookie: Mutex<Option<String>>,
 }
 
 impl HackMDCookie {
@@ -26,7 +30,9 @@ impl HackMDCookie {
       None
     };
 
-    Self { cookie }
+    Self {
+      cookie: Mutex::new(cookie),
+    }
   }
 
   /// Get a logged-in cookie.
@@ -37,8 +43,10 @@ impl HackMDCookie {
   ///
   /// * `dont_use_cache` - If true, this function ignores the cached cookie.
   pub fn get_cookie(&self, dont_use_cache: bool) -> Result<String, HackMDError> {
-    if !dont_use_cache && self.cookie.is_some() {
-      return Ok(self.cookie.clone().unwrap());
+    // Lock is held while asking user, so that other threads wait for the input
+    let mut cached_cookie = self.cookie.lock().unwrap();
+    if !dont_use_cache && cached_cookie.is_some() {
+      return Ok(cached_cookie.clone().unwrap());
     }
 
     let mut cookie = String::new();
@@ -50,9 +58,11 @@ impl HackMDCookie {
     io::stdout().flush().unwrap();
     io::stdin().read_line(&mut cookie).unwrap();
 
+    let mut cookie = cookie.trim().to_string();
     if !cookie.starts_with("connect.sid") {
       cookie = format!("connect.sid={}", cookie);
     }
+    *cached_cookie = Some(cookie.clone());
 
     Ok(cookie)
   }
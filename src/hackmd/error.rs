@@ -9,6 +9,12 @@ pub enum HackMDError {
   #[error("json parse failed")]
   JsonParseFailure(#[from] SerdeError),
 
+  #[error("file io failed")]
+  FileIoFailure(#[from] std::io::Error),
+
+  #[error("note not found: {note}")]
+  NoteNotFound { note: String },
+
   #[error("authentication error: {message:?}")]
   AuthentiocationFailure { message: String },
 }
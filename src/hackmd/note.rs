@@ -0,0 +1,139 @@
+use serde::{Deserialize, Serialize};
+
+/// Reference to a HackMD note given in command line
+#[derive(Debug, PartialEq, Clone)]
+pub enum NoteRef {
+  /// Note ID, given by `hackmd:ID` or `https://hackmd.io/ID`
+  Id(String),
+  /// Note under a user or team, given by `https://hackmd.io/@owner/note`.
+  ///
+  /// `note` can be any of note ID, short ID and permalink.
+  Path { owner: String, note: String },
+}
+
+impl NoteRef {
+  /// Parse reference to a note, or returns `None` if it is not a HackMD note
+  ///
+  /// # Arguments
+  ///
+  /// * `s` - `hackmd:ID` or URL of HackMD note
+  pub fn parse(s: &str) -> Option<Self> {
+    if let Some(id) = s.strip_prefix("hackmd:") {
+      return (!id.is_empty()).then(|| Self::Id(id.into()));
+    }
+
+    let path = s
+      .strip_prefix("https://hackmd.io/")
+      .or_else(|| s.strip_prefix("http://hackmd.io/"))?;
+    let path = path.split(['?', '#']).next().unwrap_or_default();
+    let segments = path
+      .split('/')
+      .filter(|segment| !segment.is_empty())
+      .collect::<Vec<_>>();
+    match segments.as_slice() {
+      [owner, note, ..] if owner.starts_with('@') => Some(Self::Path {
+        owner: owner[1..].into(),
+        note: note.to_string(),
+      }),
+      [id] | [id, _] if !id.starts_with('@') && !id.starts_with('_') => {
+        Some(Self::Id(id.to_string()))
+      }
+      _ => None,
+    }
+  }
+}
+
+/// Note of HackMD
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Note {
+  pub id: String,
+  #[serde(default)]
+  pub title: String,
+  /// Markdown of the note, which is omitted in lists of notes
+  #[serde(default)]
+  pub content: String,
+  #[serde(default)]
+  pub short_id: Option<String>,
+  #[serde(default)]
+  pub permalink: Option<String>,
+}
+
+impl Note {
+  /// Whether the note is referred by the key, which is any of note ID, short ID and permalink
+  pub fn is_referred_by(&self, key: &str) -> bool {
+    self.id == key
+      || self.short_id.as_deref() == Some(key)
+      || self.permalink.as_deref() == Some(key)
+  }
+
+  /// Key of the note to identify its images and entry, such as `hackmd:ID`
+  pub fn source(&self) -> String {
+    format!("hackmd:{}", self.id)
+  }
+
+  /// Name of the note usable as a file name, derived from its title or ID
+  pub fn file_stem(&self) -> String {
+    let stem = self
+      .title
+      .trim()
+      .chars()
+      .map(|c| match c {
+        '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => '-',
+        c if c.is_whitespace() => '-',
+        c => c,
+      })
+      .collect::<String>();
+    if stem.trim_matches(['-', '.']).is_empty() {
+      self.id.clone()
+    } else {
+      stem
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_parse_note_ref() {
+    assert_eq!(
+      NoteRef::parse("hackmd:abc"),
+      Some(NoteRef::Id("abc".into()))
+    );
+    assert_eq!(
+      NoteRef::parse("https://hackmd.io/@team/xyz?view"),
+      Some(NoteRef::Path {
+        owner: "team".into(),
+        note: "xyz".into(),
+      })
+    );
+    assert_eq!(
+      NoteRef::parse("https://hackmd.io/abc/edit"),
+      Some(NoteRef::Id("abc".into()))
+    );
+    assert_eq!(NoteRef::parse("https://hackmd.io/_uploads/a.png"), None);
+    assert_eq!(NoteRef::parse("hackmd:"), None);
+    assert_eq!(NoteRef::parse("notes/hackmd.md"), None);
+
+    let note = Note {
+      id: "abc".into(),
+      title: "Hello: World / 2022".into(),
+      content: "".into(),
+      short_id: Some("S1x".into()),
+      permalink: None,
+    };
+    assert!(note.is_referred_by("S1x"));
+    assert_eq!(note.source(), "hackmd:abc");
+    assert_eq!(note.file_stem(), "Hello--World---2022");
+    assert_eq!(
+      Note {
+        title: " / ".into(),
+        ..note
+      }
+      .file_stem(),
+      "abc"
+    );
+  }
+}
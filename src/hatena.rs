@@ -1,5 +1,19 @@
-use crate::error::*;
+pub mod blog;
+pub mod error;
+pub mod oauth1;
+pub mod store;
 
+use std::{thread, time::Duration};
+
+use crate::{
+  converter::image::fotolife::{cdn_url, FotolifeImage, FotolifeType},
+  error::*,
+};
+
+use self::{
+  error::{has_transient_cause, UploadError},
+  oauth1::OauthCredentials,
+};
 use colored::*;
 use hatena_rs::fotolife::Fotolife;
 use hatena_rs::oauth::{consts::OauthScope, error::OauthError, HatenaConsumerInfo, HatenaOauth};
@@ -8,10 +22,23 @@ use rpassword::prompt_password;
 /// Hatena Fotolife uploader
 pub struct HatenaUploader {
   fotolife: Fotolife,
+  consumer_key: String,
+  consumer_secret: String,
   timeout: u64,
+  /// Number of retries after the first attempt
+  retries: u32,
+  /// Wait before the first retry, which doubles on every retry
+  backoff: Duration,
   myname: Option<String>,
 }
 
+/// Image failed to be uploaded
+#[derive(Debug)]
+pub struct UploadFailure {
+  pub url: String,
+  pub error: UploadError,
+}
+
 impl HatenaUploader {
   /// Create new HatenaUploader
   ///
@@ -19,9 +46,14 @@ impl HatenaUploader {
   ///
   /// # Arguments
   ///
-  /// * `consumer_info` - Hatena consumer keys
+  /// * `consumer_key` - Hatena consumer key
+  /// * `consumer_secret` - Hatena consumer secret
   /// * `timeout` - Timeout in seconds for uploading images
-  pub fn new(consumer_info: HatenaConsumerInfo, timeout: u64) -> Result<Self, ApplicationError> {
+  pub fn new(
+    consumer_key: &str,
+    consumer_secret: &str,
+    timeout: u64,
+  ) -> Result<Self, ApplicationError> {
     let scopes = vec![
       OauthScope::WritePublic,
       OauthScope::WritePrivate,
@@ -44,46 +76,182 @@ impl HatenaUploader {
         Ok(oauth_verifier.trim().to_string())
       }
     };
+    let consumer_info = HatenaConsumerInfo::new(consumer_key, consumer_secret)?;
     let oauth = HatenaOauth::new(scopes, Some(grant_permission_callback), consumer_info).unwrap();
     let fotolife = Fotolife::new(oauth);
 
     Ok(HatenaUploader {
       fotolife,
+      consumer_key: consumer_key.into(),
+      consumer_secret: consumer_secret.into(),
       timeout,
+      retries: 0,
+      backoff: Duration::from_millis(500),
       myname: None,
     })
   }
 
-  /// Upload image to Hatena Fotolife
+  /// Set retries of uploads failed by transient errors
+  ///
+  /// # Arguments
+  ///
+  /// * `retries` - Number of retries after the first attempt
+  /// * `backoff` - Wait before the first retry
+  pub fn set_retries(&mut self, retries: u32, backoff: Duration) {
+    self.retries = retries;
+    self.backoff = backoff;
+  }
+
+  /// Upload image to Hatena Fotolife, retrying transient errors with exponential backoff
   ///
   /// # Arguments
   ///
   /// * `path` - Path to image
   /// * `title - Title of image
-  pub fn upload(&mut self, path: &std::path::Path, title: &str) -> Result<String, String> {
-    let image = self.fotolife.post_image(path, title, self.timeout).unwrap();
+  pub fn upload(&mut self, path: &std::path::Path, title: &str) -> Result<String, UploadError> {
+    let timeout = self.timeout;
+    let fotolife = &mut self.fotolife;
+    let image = retry(self.retries, self.backoff, || {
+      fotolife.post_image(path, title, timeout)
+    })?;
 
     Ok(image.image_id)
   }
 
-  pub fn fotolife_url(&mut self, image_id: &str, extension: &str) -> String {
+  /// URL of an image uploaded to Hatena Fotolife
+  ///
+  /// User information is fetched on the first call.
+  ///
+  /// # Arguments
+  ///
+  /// * `image_id` - Image ID returned by `upload`
+  /// * `extension` - Extension of the stored image, such as `png`
+  pub fn fotolife_url(&mut self, image_id: &str, extension: &str) -> Result<String, UploadError> {
+    if self.myname.is_none() {
+      self.init_profile()?;
+    }
+    let user = self.myname.as_ref().unwrap();
+    let url = match FotolifeType::from_extension(extension) {
+      Some(kind) => FotolifeImage {
+        user: user.clone(),
+        image_id: image_id.into(),
+        kind,
+      }
+      .to_url(),
+      // Images without Fotolife notation are served as they are
+      None => cdn_url(user, image_id, extension),
+    };
+
+    url.ok_or_else(|| UploadError::InvalidImageId {
+      image_id: image_id.into(),
+    })
+  }
+
+  /// Keys to sign requests to other Hatena APIs, sharing the OAuth session of Fotolife
+  pub fn credentials(&mut self) -> Result<OauthCredentials, OauthError> {
+    self.init_profile()
+  }
+
+  /// Hatena ID of the user
+  pub fn myname(&mut self) -> Result<String, OauthError> {
     if self.myname.is_none() {
-      self.init_profile().unwrap();
+      self.init_profile()?;
     }
-    let dir = &image_id[..8];
-    format!(
-      "https://cdn-ak.f.st-hatena.com/images/fotolife/s/{}/{}/{}.{}",
-      self.myname.as_ref().unwrap(),
-      dir,
-      image_id,
-      extension
-    )
+
+    Ok(self.myname.clone().unwrap())
   }
 
-  pub fn init_profile(&mut self) -> Result<(), ApplicationError> {
-    let res = self.fotolife.oauth.get_access_token(false)?;
-    self.myname = Some(res.url_name);
+  /// Fetch the access token and Hatena ID of the user, and returns keys to sign requests
+  pub fn init_profile(&mut self) -> Result<OauthCredentials, OauthError> {
+    let token = self.fotolife.oauth.get_access_token(false)?;
+    self.myname = Some(token.url_name);
+
+    Ok(OauthCredentials {
+      consumer_key: self.consumer_key.clone(),
+      consumer_secret: self.consumer_secret.clone(),
+      token: token.oauth_token,
+      token_secret: token.oauth_token_secret,
+    })
+  }
+}
+
+/// Call `f` until it succeeds, or fails with a permanent error or `retries` times in a row
+fn retry<T, E, F>(retries: u32, backoff: Duration, mut f: F) -> Result<T, E>
+where
+  E: std::error::Error + 'static,
+  F: FnMut() -> Result<T, E>,
+{
+  let mut wait = backoff;
+  let mut attempt = 0;
+  loop {
+    match f() {
+      Err(error) if attempt < retries && has_transient_cause(&error) => {
+        attempt += 1;
+        thread::sleep(wait);
+        wait *= 2;
+      }
+      result => return result,
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::{
+    io::{BufRead, BufReader, Write},
+    net::TcpListener,
+  };
+
+  /// Serve responses of the statuses in order, one for each connection
+  fn serve(statuses: &'static [u16]) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}/", listener.local_addr().unwrap());
+    std::thread::spawn(move || {
+      for status in statuses {
+        let (stream, _) = listener.accept().unwrap();
+        let mut reader = BufReader::new(stream);
+        let mut line = String::new();
+        while reader.read_line(&mut line).unwrap() > 2 {
+          line.clear();
+        }
+        write!(
+          reader.get_mut(),
+          "HTTP/1.1 {} Status\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+          status
+        )
+        .unwrap();
+      }
+    });
+    url
+  }
+
+  #[test]
+  fn test_retry_only_transient_errors() {
+    let request = |url: &str, attempts: &mut u32| {
+      *attempts += 1;
+      reqwest::blocking::get(url)?.error_for_status()
+    };
+
+    let url = serve(&[503, 429, 200]);
+    let mut attempts = 0;
+    let result = retry(3, Duration::from_millis(1), || request(&url, &mut attempts));
+    assert!(result.is_ok());
+    assert_eq!(attempts, 3);
+
+    let url = serve(&[400, 200]);
+    let mut attempts = 0;
+    let result = retry(3, Duration::from_millis(1), || request(&url, &mut attempts));
+    assert_eq!(result.unwrap_err().status().unwrap().as_u16(), 400);
+    assert_eq!(attempts, 1);
 
-    Ok(())
+    // Other causes than requests are permanent
+    let mut attempts = 0;
+    let result: Result<(), UploadError> = retry(3, Duration::from_millis(1), || {
+      attempts += 1;
+      Err(std::io::Error::from(std::io::ErrorKind::NotFound).into())
+    });
+    assert!(result.is_err());
+    assert_eq!(attempts, 1);
   }
 }
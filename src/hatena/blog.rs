@@ -0,0 +1,588 @@
+use std::time::Duration;
+
+use chrono::Utc;
+use quick_xml::{
+  events::{BytesStart, Event},
+  Reader,
+};
+use reqwest::{blocking::Client, Method, Url};
+use sha2::{Digest, Sha256};
+
+use super::{
+  error::PublishError,
+  oauth1::OauthCredentials,
+  store::{EntryStore, StoredEntry},
+};
+use crate::util::escape_html;
+
+/// Entry to post to Hatena Blog
+#[derive(Debug, PartialEq, Clone)]
+pub struct Entry {
+  pub title: String,
+  /// Hatena ID of the author
+  pub author: String,
+  /// Converted HTML
+  pub content: String,
+  pub categories: Vec<String>,
+  /// Whether the entry is posted as a draft
+  pub draft: bool,
+}
+
+impl Entry {
+  /// Atom entry document of the entry
+  pub fn to_xml(&self) -> String {
+    let categories = self
+      .categories
+      .iter()
+      .map(|category| format!(r#"  <category term="{}" />"#, escape_html(category)))
+      .collect::<Vec<_>>()
+      .join("\n");
+    format!(
+      r#"<?xml version="1.0" encoding="utf-8"?>
+<entry xmlns="http://www.w3.org/2005/Atom" xmlns:app="http://www.w3.org/2007/app">
+  <title>{}</title>
+  <author><name>{}</name></author>
+  <content type="text/html">{}</content>
+{}
+  <app:control>
+    <app:draft>{}</app:draft>
+  </app:control>
+</entry>
+"#,
+      escape_html(&self.title),
+      escape_html(&self.author),
+      escape_html(&self.content),
+      categories,
+      if self.draft { "yes" } else { "no" }
+    )
+  }
+}
+
+/// Entry created on Hatena Blog
+#[derive(Debug, PartialEq, Clone)]
+pub struct PublishedEntry {
+  /// Atom ID such as `tag:blog.hatena.ne.jp,2013:blog-user-123-456`
+  pub id: String,
+  /// URL to edit the entry via AtomPub
+  pub edit_url: String,
+  /// URL of the entry on the blog
+  pub url: Option<String>,
+  /// SHA-256 of the content stored on Hatena Blog, such as `sha256:...`
+  pub content_hash: String,
+}
+
+impl PublishedEntry {
+  /// Parse Atom entry document responded by Hatena Blog
+  pub fn from_xml(xml: &str) -> Result<Self, PublishError> {
+    let invalid = |message: String| PublishError::InvalidResponse { message };
+
+    let mut reader = Reader::from_str(xml);
+    // Local names of elements enclosing the current position
+    let mut path: Vec<Vec<u8>> = vec![];
+    let mut text = String::new();
+    let (mut id, mut edit_url, mut url, mut content) = (None, None, None, None);
+    loop {
+      let event = reader.read_event().map_err(|e| invalid(e.to_string()))?;
+      match event {
+        Event::Start(ref e) | Event::Empty(ref e) => {
+          let name = e.local_name().as_ref().to_vec();
+          if path.is_empty() && name != b"entry" {
+            return Err(invalid("response is not an Atom entry".into()));
+          }
+          if path.len() == 1 && name == b"link" {
+            let (rel, href) = link(e).map_err(invalid)?;
+            match rel.as_str() {
+              "edit" => edit_url = href,
+              "alternate" => url = href,
+              _ => (),
+            }
+          }
+          if matches!(event, Event::Start(_)) {
+            path.push(name);
+            text.clear();
+          }
+        }
+        Event::Text(e) if path.len() == 2 => {
+          text.push_str(&e.unescape().map_err(|e| invalid(e.to_string()))?)
+        }
+        Event::CData(e) if path.len() == 2 => {
+          text.push_str(&String::from_utf8_lossy(&e.into_inner()))
+        }
+        Event::End(_) => {
+          let name = path.pop().unwrap_or_default();
+          if path.len() == 1 {
+            match name.as_slice() {
+              b"id" => id = Some(text.trim().to_string()),
+              b"content" => content = Some(std::mem::take(&mut text)),
+              _ => (),
+            }
+          }
+        }
+        Event::Eof => break,
+        _ => (),
+      }
+    }
+
+    Ok(Self {
+      id: id.ok_or_else(|| invalid("entry has no id".into()))?,
+      edit_url: edit_url.ok_or_else(|| invalid("entry has no edit link".into()))?,
+      url,
+      content_hash: content_hash(&content.unwrap_or_default()),
+    })
+  }
+}
+
+/// Relation and `href` of `<link>`, where the relation is `alternate` if omitted
+fn link(element: &BytesStart) -> Result<(String, Option<String>), String> {
+  let value = |name: &str| -> Result<Option<String>, String> {
+    match element.try_get_attribute(name).map_err(|e| e.to_string())? {
+      Some(attribute) => Ok(Some(
+        attribute
+          .unescape_value()
+          .map_err(|e| e.to_string())?
+          .into_owned(),
+      )),
+      None => Ok(None),
+    }
+  };
+
+  Ok((
+    value("rel")?.unwrap_or_else(|| "alternate".into()),
+    value("href")?,
+  ))
+}
+
+/// Client of Hatena Blog AtomPub API
+pub struct HatenaBlog {
+  /// Root endpoint such as `https://blog.hatena.ne.jp/{user}/{blog}/atom`
+  endpoint: String,
+  credentials: OauthCredentials,
+  client: Client,
+}
+
+impl HatenaBlog {
+  /// Create new HatenaBlog
+  ///
+  /// # Arguments
+  ///
+  /// * `endpoint` - Root endpoint of AtomPub API
+  /// * `credentials` - Keys to sign requests
+  /// * `timeout` - Timeout in seconds for each request
+  pub fn new(endpoint: &str, credentials: OauthCredentials, timeout: u64) -> Self {
+    let client = Client::builder()
+      .timeout(Duration::from_secs(timeout))
+      .build()
+      .unwrap();
+
+    Self {
+      endpoint: endpoint.trim_end_matches('/').into(),
+      credentials,
+      client,
+    }
+  }
+
+  /// Root endpoint of AtomPub API of a blog
+  ///
+  /// # Arguments
+  ///
+  /// * `user` - Hatena ID of the owner
+  /// * `blog_id` - Domain of the blog, such as `example.hatenablog.com`
+  pub fn default_endpoint(user: &str, blog_id: &str) -> String {
+    format!("https://blog.hatena.ne.jp/{}/{}/atom", user, blog_id)
+  }
+
+  /// Create an entry
+  pub fn post(&self, entry: &Entry) -> Result<PublishedEntry, PublishError> {
+    let url = format!("{}/entry", self.endpoint);
+    self.send(Method::POST, &url, Some(entry.to_xml()))
+  }
+
+  /// Fetch an entry
+  ///
+  /// # Arguments
+  ///
+  /// * `edit_url` - URL to edit the entry
+  pub fn get(&self, edit_url: &str) -> Result<PublishedEntry, PublishError> {
+    self.send(Method::GET, edit_url, None)
+  }
+
+  /// Replace an entry
+  ///
+  /// # Arguments
+  ///
+  /// * `edit_url` - URL to edit the entry
+  /// * `entry` - New entry
+  pub fn update(&self, edit_url: &str, entry: &Entry) -> Result<PublishedEntry, PublishError> {
+    self.send(Method::PUT, edit_url, Some(entry.to_xml()))
+  }
+
+  /// Publish an entry of a source, updating the entry published from it before.
+  ///
+  /// The entry is updated only if it is not edited on Hatena Blog after the last publish,
+  /// unless `force` is given. Entry deleted on Hatena Blog is created again.
+  /// The published entry is recorded in `store`.
+  ///
+  /// # Arguments
+  ///
+  /// * `entry` - Entry to publish
+  /// * `source` - Source of the entry, such as absolute path of markdown file
+  /// * `store` - Store of entries published before
+  /// * `force` - Overwrite the entry even if it is edited on Hatena Blog
+  /// * `warnings` - Warnings to be pushed
+  pub fn publish(
+    &self,
+    entry: &Entry,
+    source: &str,
+    store: &mut EntryStore,
+    force: bool,
+    warnings: &mut Vec<String>,
+  ) -> Result<PublishedEntry, PublishError> {
+    let published = match store.find(source) {
+      Some(stored) => match self.get(&stored.edit_url) {
+        Ok(remote) => {
+          if remote.content_hash != stored.content_hash && !force {
+            return Err(PublishError::RemoteEdited {
+              edit_url: stored.edit_url.clone(),
+            });
+          }
+          self.update(&stored.edit_url, entry)?
+        }
+        Err(PublishError::Rejected { status: 404, .. }) => {
+          warnings.push(format!(
+            "entry {} is not found, creating new one",
+            stored.edit_url
+          ));
+          self.post(entry)?
+        }
+        Err(e) => return Err(e),
+      },
+      None => self.post(entry)?,
+    };
+
+    store.insert(StoredEntry {
+      source: source.into(),
+      entry_id: published.id.clone(),
+      edit_url: published.edit_url.clone(),
+      url: published.url.clone(),
+      content_hash: published.content_hash.clone(),
+      published_at: Some(Utc::now()),
+    });
+
+    Ok(published)
+  }
+
+  /// Send a signed request, and parse the responded entry
+  fn send(
+    &self,
+    method: Method,
+    url: &str,
+    body: Option<String>,
+  ) -> Result<PublishedEntry, PublishError> {
+    let url = Url::parse(url).map_err(|e| PublishError::InvalidEndpoint {
+      message: e.to_string(),
+    })?;
+    let mut req = self.client.request(method.clone(), url.clone()).header(
+      reqwest::header::AUTHORIZATION,
+      self.credentials.authorization(method.as_str(), &url),
+    );
+    if let Some(body) = body {
+      req = req
+        .header(
+          reqwest::header::CONTENT_TYPE,
+          "application/atom+xml; type=entry",
+        )
+        .body(body);
+    }
+    let res = req.send()?;
+
+    let status = res.status();
+    let body = res.text()?;
+    if !status.is_success() {
+      return Err(PublishError::Rejected {
+        status: status.as_u16(),
+        message: body.trim().into(),
+      });
+    }
+
+    PublishedEntry::from_xml(&body)
+  }
+}
+
+/// Hash of content of entry, such as `sha256:...`
+pub fn content_hash(content: &str) -> String {
+  format!("sha256:{:x}", Sha256::digest(content.trim().as_bytes()))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::{
+    io::{BufRead, BufReader, Read, Write},
+    net::TcpListener,
+    thread::JoinHandle,
+  };
+
+  /// Request received by the stub server
+  struct Request {
+    head: Vec<String>,
+    body: String,
+  }
+
+  /// Serve responses in order, one for each connection,
+  /// and returns the root endpoint and received requests
+  ///
+  /// # Arguments
+  ///
+  /// * `responses` - Statuses and bodies of responses, given the root endpoint
+  fn serve<F>(responses: F) -> (String, JoinHandle<Vec<Request>>)
+  where
+    F: FnOnce(&str) -> Vec<(u16, String)>,
+  {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let endpoint = format!(
+      "http://{}/user/example.hatenablog.com/atom",
+      listener.local_addr().unwrap()
+    );
+    let responses = responses(&endpoint);
+    let server = std::thread::spawn(move || {
+      let mut requests = vec![];
+      for (status, response) in responses {
+        let (stream, _) = listener.accept().unwrap();
+        let mut reader = BufReader::new(stream);
+        let mut head = vec![];
+        loop {
+          let mut line = String::new();
+          reader.read_line(&mut line).unwrap();
+          if line.trim().is_empty() {
+            break;
+          }
+          head.push(line);
+        }
+        let length = head
+          .iter()
+          .find_map(|line| {
+            line
+              .to_lowercase()
+              .strip_prefix("content-length:")
+              .map(|n| n.trim().parse::<usize>().unwrap())
+          })
+          .unwrap_or(0);
+        let mut body = vec![0; length];
+        reader.read_exact(&mut body).unwrap();
+
+        write!(
+          reader.get_mut(),
+          "HTTP/1.1 {} Status\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+          status,
+          response.len(),
+          response
+        )
+        .unwrap();
+        requests.push(Request {
+          head,
+          body: String::from_utf8(body).unwrap(),
+        });
+      }
+      requests
+    });
+
+    (endpoint, server)
+  }
+
+  /// Atom entry responded by Hatena Blog
+  fn entry_xml(edit_url: &str, content: &str) -> String {
+    format!(
+      r#"<?xml version="1.0" encoding="utf-8"?>
+<entry xmlns="http://www.w3.org/2005/Atom">
+  <id>tag:blog.hatena.ne.jp,2013:blog-user-1-2</id>
+  <link rel="edit" href="{}"/>
+  <link rel="alternate" type="text/html" href="https://example.hatenablog.com/entry/2022/10/10/123456"/>
+  <content type="text/html">{}</content>
+</entry>"#,
+      edit_url,
+      escape_html(content)
+    )
+  }
+
+  fn credentials() -> OauthCredentials {
+    OauthCredentials {
+      consumer_key: "key".into(),
+      consumer_secret: "secret".into(),
+      token: "token".into(),
+      token_secret: "token_secret".into(),
+    }
+  }
+
+  #[test]
+  fn test_post_entry_to_stub() {
+    let edit_url = "https://blog.hatena.ne.jp/user/example.hatenablog.com/atom/entry/2";
+    let (endpoint, server) = serve(|_| vec![(201, entry_xml(edit_url, "<p>body</p>"))]);
+
+    let entry = Entry {
+      title: "Hello & Goodbye".into(),
+      author: "user".into(),
+      content: "<p>body</p>".into(),
+      categories: vec!["rust".into(), "hatena".into()],
+      draft: true,
+    };
+    let published = HatenaBlog::new(&endpoint, credentials(), 5)
+      .post(&entry)
+      .unwrap();
+    let requests = server.join().unwrap();
+    let (head, body) = (&requests[0].head, &requests[0].body);
+
+    assert!(head[0].starts_with("POST /user/example.hatenablog.com/atom/entry "));
+    assert!(head.iter().any(|line| line
+      .to_lowercase()
+      .starts_with("authorization: oauth oauth_consumer_key=\"key\"")));
+    assert!(body.contains("<title>Hello &amp; Goodbye</title>"));
+    assert!(body.contains("<content type=\"text/html\">&lt;p&gt;body&lt;/p&gt;</content>"));
+    assert!(body.contains(r#"<category term="hatena" />"#));
+    assert!(body.contains("<app:draft>yes</app:draft>"));
+    assert_eq!(
+      published,
+      PublishedEntry {
+        id: "tag:blog.hatena.ne.jp,2013:blog-user-1-2".into(),
+        edit_url: edit_url.into(),
+        url: Some("https://example.hatenablog.com/entry/2022/10/10/123456".into()),
+        content_hash: content_hash("<p>body</p>"),
+      }
+    );
+  }
+
+  #[test]
+  fn test_publish_entry() {
+    let entry = Entry {
+      title: "Hello".into(),
+      author: "user".into(),
+      content: "<p>new</p>".into(),
+      categories: vec![],
+      draft: false,
+    };
+    let stored = |endpoint: &str| StoredEntry {
+      source: "/notes/a.md".into(),
+      entry_id: "tag:blog.hatena.ne.jp,2013:blog-user-1-2".into(),
+      edit_url: format!("{}/entry/2", endpoint),
+      url: None,
+      content_hash: content_hash("<p>old</p>"),
+      published_at: None,
+    };
+    let publish = |endpoint: &str, force: bool| {
+      let mut store = EntryStore::new();
+      store.insert(stored(endpoint));
+      let mut warnings = vec![];
+      let result = HatenaBlog::new(endpoint, credentials(), 5).publish(
+        &entry,
+        "/notes/a.md",
+        &mut store,
+        force,
+        &mut warnings,
+      );
+      (result, store.find("/notes/a.md").unwrap().clone(), warnings)
+    };
+    let request_lines = |server: JoinHandle<Vec<Request>>, endpoint: &str| {
+      let root = endpoint.split('/').take(3).collect::<Vec<_>>().join("/");
+      server
+        .join()
+        .unwrap()
+        .iter()
+        .map(|request| {
+          let mut line = request.head[0].split(' ');
+          format!("{} {}{}", line.next().unwrap(), root, line.next().unwrap())
+        })
+        .collect::<Vec<_>>()
+    };
+
+    // Entry not edited on Hatena Blog is updated
+    let (endpoint, server) = serve(|endpoint| {
+      let edit_url = format!("{}/entry/2", endpoint);
+      vec![
+        (200, entry_xml(&edit_url, "<p>old</p>")),
+        (200, entry_xml(&edit_url, "<p>new</p>")),
+      ]
+    });
+    let (result, stored_entry, _) = publish(&endpoint, false);
+    assert!(result.is_ok());
+    assert_eq!(stored_entry.content_hash, content_hash("<p>new</p>"));
+    assert_eq!(
+      request_lines(server, &endpoint),
+      [
+        format!("GET {}/entry/2", endpoint),
+        format!("PUT {}/entry/2", endpoint)
+      ]
+    );
+
+    // Entry edited on Hatena Blog is refused, unless forced
+    let (endpoint, server) = serve(|endpoint| {
+      vec![(
+        200,
+        entry_xml(&format!("{}/entry/2", endpoint), "<p>edited</p>"),
+      )]
+    });
+    let (result, stored_entry, _) = publish(&endpoint, false);
+    assert!(matches!(result, Err(PublishError::RemoteEdited { .. })));
+    assert_eq!(stored_entry, stored(&endpoint));
+    assert_eq!(request_lines(server, &endpoint).len(), 1);
+
+    let (endpoint, server) = serve(|endpoint| {
+      let edit_url = format!("{}/entry/2", endpoint);
+      vec![
+        (200, entry_xml(&edit_url, "<p>edited</p>")),
+        (200, entry_xml(&edit_url, "<p>new</p>")),
+      ]
+    });
+    let (result, stored_entry, _) = publish(&endpoint, true);
+    assert!(result.is_ok());
+    assert_eq!(stored_entry.content_hash, content_hash("<p>new</p>"));
+    assert_eq!(
+      request_lines(server, &endpoint)[1],
+      format!("PUT {}/entry/2", endpoint)
+    );
+
+    // Entry deleted on Hatena Blog is created again
+    let (endpoint, server) = serve(|endpoint| {
+      vec![
+        (404, "Not Found".into()),
+        (
+          201,
+          entry_xml(&format!("{}/entry/3", endpoint), "<p>new</p>"),
+        ),
+      ]
+    });
+    let (result, stored_entry, warnings) = publish(&endpoint, false);
+    assert!(result.is_ok());
+    assert_eq!(stored_entry.edit_url, format!("{}/entry/3", endpoint));
+    assert_eq!(warnings.len(), 1);
+    assert_eq!(
+      request_lines(server, &endpoint),
+      [
+        format!("GET {}/entry/2", endpoint),
+        format!("POST {}/entry", endpoint)
+      ]
+    );
+  }
+
+  #[test]
+  fn test_parse_entry() {
+    let xml = r#"<?xml version="1.0" encoding="utf-8"?>
+<entry xmlns='http://www.w3.org/2005/Atom'>
+  <id>tag:blog.hatena.ne.jp,2013:blog-user-1-2</id>
+  <link rel='edit' href='https://blog.hatena.ne.jp/user/blog/atom/entry/2?a=1&amp;b=2'/>
+  <link href="https://example.hatenablog.com/entry/2"></link>
+  <author><name>user</name><id>not the entry id</id></author>
+  <content type="text/html"><![CDATA[<p>It&#39;s </p>]]>&lt;b&gt;&#x263A;&lt;/b&gt;</content>
+</entry>"#;
+    assert_eq!(
+      PublishedEntry::from_xml(xml).unwrap(),
+      PublishedEntry {
+        id: "tag:blog.hatena.ne.jp,2013:blog-user-1-2".into(),
+        edit_url: "https://blog.hatena.ne.jp/user/blog/atom/entry/2?a=1&b=2".into(),
+        url: Some("https://example.hatenablog.com/entry/2".into()),
+        content_hash: content_hash("<p>It&#39;s </p><b>\u{263A}</b>"),
+      }
+    );
+
+    assert!(PublishedEntry::from_xml("<feed><id>1</id></feed>").is_err());
+    assert!(PublishedEntry::from_xml("<entry><id>1</id></entry>").is_err());
+  }
+}
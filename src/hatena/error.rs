@@ -0,0 +1,60 @@
+use std::error::Error;
+
+use hatena_rs::oauth::error::OauthError;
+use thiserror::Error;
+
+use crate::util::is_transient_request;
+
+#[derive(Debug, Error)]
+pub enum UploadError {
+  #[error("request to Fotolife failed: {0}")]
+  RequestFailure(#[from] OauthError),
+
+  #[error("file io failed: {0}")]
+  FileIoFailure(#[from] std::io::Error),
+
+  #[error("image processing failed: {0}")]
+  ImageProcessFailure(#[from] image::ImageError),
+
+  #[error("format of {path} is unknown")]
+  UnknownFormat { path: String },
+
+  #[error("Fotolife returned unexpected image ID: {image_id}")]
+  InvalidImageId { image_id: String },
+}
+
+/// Whether the error is caused by a request which may succeed by retrying.
+///
+/// Only connection failures, timeouts, server errors and 429 are transient.
+/// Others, such as bad credentials and rejected images, fail again.
+pub fn has_transient_cause(error: &(dyn Error + 'static)) -> bool {
+  let mut cause = Some(error);
+  while let Some(error) = cause {
+    if let Some(e) = error.downcast_ref::<reqwest::Error>() {
+      return is_transient_request(e);
+    }
+    cause = error.source();
+  }
+  false
+}
+
+#[derive(Debug, Error)]
+pub enum PublishError {
+  #[error("request to Hatena Blog failed: {0}")]
+  RequestFailure(#[from] reqwest::Error),
+
+  #[error("authorization failed: {0}")]
+  OauthFailure(#[from] OauthError),
+
+  #[error("Hatena Blog responded {status}: {message}")]
+  Rejected { status: u16, message: String },
+
+  #[error("entry {edit_url} was edited on Hatena Blog after the last publish, use --force to overwrite it")]
+  RemoteEdited { edit_url: String },
+
+  #[error("invalid endpoint: {message}")]
+  InvalidEndpoint { message: String },
+
+  #[error("unexpected response: {message}")]
+  InvalidResponse { message: String },
+}
@@ -0,0 +1,150 @@
+use base64::{engine::general_purpose::STANDARD, Engine};
+use hmac::{Hmac, Mac};
+use percent_encoding::{utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
+use sha1::Sha1;
+
+use crate::util;
+
+/// Characters to be encoded in OAuth 1.0, which are all but unreserved ones of RFC 3986
+const OAUTH_ENCODE_SET: &AsciiSet = &NON_ALPHANUMERIC
+  .remove(b'-')
+  .remove(b'.')
+  .remove(b'_')
+  .remove(b'~');
+
+/// Keys to sign requests with OAuth 1.0
+#[derive(Debug, Clone)]
+pub struct OauthCredentials {
+  pub consumer_key: String,
+  pub consumer_secret: String,
+  pub token: String,
+  pub token_secret: String,
+}
+
+impl OauthCredentials {
+  /// Returns value of `Authorization` header for a request
+  ///
+  /// # Arguments
+  ///
+  /// * `method` - HTTP method such as `POST`
+  /// * `url` - URL of the request, whose query parameters are also signed
+  pub fn authorization(&self, method: &str, url: &reqwest::Url) -> String {
+    let timestamp = chrono::Utc::now().timestamp().to_string();
+    self.authorization_with(method, url, &util::gen_uuid(), &timestamp, &[])
+  }
+
+  /// Returns value of `Authorization` header with given nonce and timestamp
+  ///
+  /// # Arguments
+  ///
+  /// * `params` - Form parameters of the body, which are signed but not put in the header
+  fn authorization_with(
+    &self,
+    method: &str,
+    url: &reqwest::Url,
+    nonce: &str,
+    timestamp: &str,
+    params: &[(&str, &str)],
+  ) -> String {
+    let mut oauth_params = vec![
+      ("oauth_consumer_key", self.consumer_key.as_str()),
+      ("oauth_nonce", nonce),
+      ("oauth_signature_method", "HMAC-SHA1"),
+      ("oauth_timestamp", timestamp),
+      ("oauth_token", self.token.as_str()),
+      ("oauth_version", "1.0"),
+    ];
+    let signature = self.signature(method, url, &oauth_params, params);
+    oauth_params.push(("oauth_signature", &signature));
+
+    let header = oauth_params
+      .iter()
+      .map(|(key, value)| format!(r#"{}="{}""#, key, encode(value)))
+      .collect::<Vec<_>>()
+      .join(", ");
+    format!("OAuth {}", header)
+  }
+
+  /// Signature of a request with HMAC-SHA1
+  fn signature(
+    &self,
+    method: &str,
+    url: &reqwest::Url,
+    oauth_params: &[(&str, &str)],
+    params: &[(&str, &str)],
+  ) -> String {
+    let query = url.query_pairs().collect::<Vec<_>>();
+    let mut pairs = oauth_params
+      .iter()
+      .chain(params)
+      .map(|(key, value)| (encode(key), encode(value)))
+      .chain(
+        query
+          .iter()
+          .map(|(key, value)| (encode(key), encode(value))),
+      )
+      .collect::<Vec<_>>();
+    pairs.sort();
+    let normalized_params = pairs
+      .iter()
+      .map(|(key, value)| format!("{}={}", key, value))
+      .collect::<Vec<_>>()
+      .join("&");
+
+    let mut base_url = url.clone();
+    base_url.set_query(None);
+    base_url.set_fragment(None);
+    let base_string = format!(
+      "{}&{}&{}",
+      method.to_uppercase(),
+      encode(base_url.as_str()),
+      encode(&normalized_params)
+    );
+    let key = format!(
+      "{}&{}",
+      encode(&self.consumer_secret),
+      encode(&self.token_secret)
+    );
+
+    let mut mac = Hmac::<Sha1>::new_from_slice(key.as_bytes()).unwrap();
+    mac.update(base_string.as_bytes());
+    STANDARD.encode(mac.finalize().into_bytes())
+  }
+}
+
+/// Percent-encode a string as OAuth 1.0 requires
+fn encode(s: &str) -> String {
+  utf8_percent_encode(s, OAUTH_ENCODE_SET).to_string()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_signature() {
+    // Example of Twitter's documentation on signing requests
+    let credentials = OauthCredentials {
+      consumer_key: "xvz1evFS4wEEPTGEFPHBog".into(),
+      consumer_secret: "kAcSOqF21Fu85e7zjz7ZN2U4ZRhfV3WpwPAoE3Z7kBw".into(),
+      token: "370773112-GmHxMAgYyLbNEtIKZeRNFsMKPR9EyMZeS9weJAEb".into(),
+      token_secret: "LswwdoUaIvS8ltyTt5jkRh4J50vUPVVHtR2YPi5kE".into(),
+    };
+    let url =
+      reqwest::Url::parse("https://api.twitter.com/1.1/statuses/update.json?include_entities=true")
+        .unwrap();
+    let authorization = credentials.authorization_with(
+      "post",
+      &url,
+      "kYjzVBB8Y0ZFabxSWbWovY3uYSQ2pTgmZeNu2VS4cg",
+      "1318622958",
+      &[(
+        "status",
+        "Hello Ladies + Gentlemen, a signed OAuth request!",
+      )],
+    );
+
+    assert!(authorization.starts_with(r#"OAuth oauth_consumer_key="xvz1evFS4wEEPTGEFPHBog", "#));
+    assert!(authorization.ends_with(r#"oauth_signature="hCtSmYh%2BiHYCEqBWrE7C7hYmtUk%3D""#));
+  }
+}
@@ -0,0 +1,68 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::store::VersionedStore;
+
+/// Entry of Hatena Blog published from a markdown
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct StoredEntry {
+  /// Source of the entry, such as absolute path of markdown file
+  pub source: String,
+  /// Atom ID of the entry
+  pub entry_id: String,
+  pub edit_url: String,
+  #[serde(default)]
+  pub url: Option<String>,
+  /// SHA-256 of the content on Hatena Blog at the last publish, such as `sha256:...`
+  pub content_hash: String,
+  #[serde(default)]
+  pub published_at: Option<DateTime<Utc>>,
+}
+
+/// Versioned JSON store of entries published to Hatena Blog
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct EntryStore {
+  pub version: u32,
+  pub entries: Vec<StoredEntry>,
+}
+
+impl Default for EntryStore {
+  fn default() -> Self {
+    Self {
+      version: Self::VERSION,
+      entries: vec![],
+    }
+  }
+}
+
+impl VersionedStore for EntryStore {
+  const VERSION: u32 = 1;
+  const NAME: &'static str = "entry store";
+
+  fn version(&self) -> u32 {
+    self.version
+  }
+}
+
+impl EntryStore {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  /// Find an entry by its source
+  pub fn find(&self, source: &str) -> Option<&StoredEntry> {
+    self.entries.iter().find(|entry| entry.source == source)
+  }
+
+  /// Insert an entry, or replace the entry having the same source
+  pub fn insert(&mut self, entry: StoredEntry) {
+    match self
+      .entries
+      .iter_mut()
+      .find(|stored| stored.source == entry.source)
+    {
+      Some(stored) => *stored = entry,
+      None => self.entries.push(entry),
+    }
+  }
+}
@@ -1,7 +1,10 @@
+pub mod cache;
 pub mod cli;
 pub mod config;
 pub mod converter;
+pub mod download;
 pub mod error;
 pub mod hackmd;
 pub mod hatena;
+pub mod store;
 pub mod util;
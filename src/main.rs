@@ -1,68 +1,176 @@
-use std::path;
+use std::{path, time::Duration};
 
 use clap::Parser;
 use md2hatena::{
   cli::{
-    download_images, get_hackmd_api_token, get_hatena_api_token, panic_with_error,
-    read_markdown_file, upload_images, write_result_html, Args,
+    download_images, get_hackmd_api_token, get_hatena_api_token, panic_with_error, print_warnings,
+    publish_entry, read_document, run_cache_command, upload_images, write_result_html, Args,
+    Command,
   },
   config::Config,
-  converter::{self, image::ResolvedImage},
+  converter::{
+    self,
+    front_matter::FrontMatter,
+    image::{process::ImageProcessor, source::ImageSource, store::ImageStore, ResolvedImage},
+  },
+  download::Downloader,
   error::ApplicationError,
   hackmd, hatena,
+  store::VersionedStore,
 };
 
 fn process() -> Result<(), ApplicationError> {
   let args = Args::parse();
-  let config = Config::from_args(&args)?;
+  // Front matter is applied after the note is read
+  let mut config = Config::from_args(&args, None)?;
+  if let Some(Command::Cache { command }) = &args.command {
+    return run_cache_command(command, &config);
+  }
 
   let hackmd_apitoken = get_hackmd_api_token();
-  let hatena_apitoken = get_hatena_api_token();
-  let markdown = read_markdown_file(&args.markdown_path);
+  let mut hackmd = hackmd::HackMD::new(hackmd_apitoken);
+  hackmd.set_timeout(config.download_timeout);
+
+  // Markdown path is required unless subcommand is given
+  let document = read_document(args.markdown_path.as_deref().unwrap(), &hackmd)?;
+  let mut warnings = vec![];
+  let (front_matter, markdown) = FrontMatter::parse(&document.markdown, &mut warnings);
+  print_warnings(&warnings);
+  if let Some(front_matter) = &front_matter {
+    config.apply_front_matter(&args, front_matter);
+  }
+  config.set_output(&args, &document.name);
+
+  let (consumer_key, consumer_secret) = get_hatena_api_token();
+  let mut fotolife = hatena::HatenaUploader::new(&consumer_key, &consumer_secret, config.timeout)?;
 
-  let hackmd = hackmd::HackMD::new(hackmd_apitoken);
-  let mut fotolife = hatena::HatenaUploader::new(hatena_apitoken, config.timeout)?;
+  let processor = ImageProcessor::new(&config)?;
 
   let mut converter = converter::Converter::new(&config);
-  converter.parse(&markdown).unwrap();
+  converter.parse_body(front_matter, &markdown).unwrap();
+  print_warnings(&converter.warnings);
 
   let do_image_cache = !config.image_mapping.is_empty();
   if !args.no_resolve {
+    // Local images are resolved relative to the markdown file
+    let base_dir = &document.base_dir;
+    let mut images = vec![];
+    let mut warnings = vec![];
+    for url in &converter.unresolved_images {
+      match ImageSource::from(url, base_dir) {
+        Ok(source) => images.push((url.clone(), source)),
+        Err(message) => warnings.push(message),
+      }
+    }
+    print_warnings(&warnings);
+
     // Resolve images using cached ones first
-    let cached_images = if do_image_cache {
-      ResolvedImage::restore_from(&config.image_mapping)?
+    let mut store = if do_image_cache {
+      ImageStore::open(&config.image_mapping)?
     } else {
-      vec![]
+      ImageStore::new()
     };
-    converter.resolve_images(&cached_images);
+    converter.resolve_images(&ResolvedImage::resolve_cached(&images, &store));
+    for (_, source) in &images {
+      if let Ok(key) = source.cache_key() {
+        store.reference(&key, &document.source);
+      }
+    }
+    if do_image_cache {
+      store.save(&config.image_mapping)?;
+    }
+    let (unresolved_images, sources): (Vec<_>, Vec<_>) = images
+      .into_iter()
+      .filter(|(url, _)| converter.unresolved_images.contains(url))
+      .unzip();
 
-    // Download images
-    let unresolved_images = converter.unresolved_images.clone();
-    download_images(
-      &unresolved_images,
+    // Download remote images
+    let remote_images = sources
+      .iter()
+      .filter_map(|source| match source {
+        ImageSource::Remote(url) => Some(url.clone()),
+        _ => None,
+      })
+      .collect::<Vec<_>>();
+    let downloader = Downloader::new(
+      config.download_concurrency,
+      config.download_retries,
+      Duration::from_millis(500),
+    );
+    let failed_images = download_images(
+      &remote_images,
       path::Path::new(&config.download_dir),
       &hackmd,
       false,
+      &downloader,
     );
+    // Images failed to be downloaded are left as they are
+    let images = unresolved_images
+      .into_iter()
+      .zip(sources)
+      .filter(|(_, source)| match source {
+        ImageSource::Remote(url) => !failed_images.contains(url),
+        _ => true,
+      })
+      .collect::<Vec<_>>();
+    // Decode data URI images
+    for (_, source) in &images {
+      source.save(path::Path::new(&config.download_dir))?;
+    }
 
     // Upload images
-    let fotolife_ids = upload_images(
-      &unresolved_images,
+    fotolife.set_retries(config.upload_retries, Duration::from_millis(500));
+    let (resolved_images, failures) = upload_images(
+      &images,
       path::Path::new(&config.download_dir),
+      &processor,
       &mut fotolife,
       false,
       &config.image_mapping,
+      &mut store,
+      &document.source,
     )?;
 
-    // Resolve images
-    let resolved_images = ResolvedImage::from(unresolved_images, fotolife_ids);
+    // Resolve images, leaving placeholders for ones failed to be uploaded
     converter.resolve_images(&resolved_images);
+    let failed_images = failures
+      .into_iter()
+      .map(|failure| failure.url)
+      .collect::<Vec<_>>();
+    converter.fail_images(&failed_images);
+    if !failed_images.is_empty() && do_image_cache {
+      print_warnings(&[format!(
+        "{} images failed to be uploaded, rerun to upload only them",
+        failed_images.len()
+      )]);
+    }
   }
 
   // Convert to HTML
   let html = converter.convert().unwrap();
   write_result_html(&html, &config.output);
 
+  if let Some(visibility) = &args.publish {
+    // Placeholders of failed images and local paths must not go live
+    if visibility != "draft" && !converter.unresolved_images.is_empty() {
+      return Err(ApplicationError::MiscError {
+        message: format!(
+          "{} images are not uploaded to Fotolife, publish them as a draft with `--publish draft`",
+          converter.unresolved_images.len()
+        ),
+      });
+    }
+    publish_entry(
+      &html,
+      converter.front_matter.as_ref(),
+      &document,
+      &config,
+      &mut fotolife,
+      visibility == "draft",
+      args.force,
+    )?;
+  }
+
   Ok(())
 }
 
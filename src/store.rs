@@ -0,0 +1,124 @@
+use std::path::Path;
+
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::error::ApplicationError;
+
+/// Store saved as a JSON file with the version of its format
+pub trait VersionedStore: Default + Serialize + DeserializeOwned {
+  /// Current version of the format. Stores of newer versions are refused.
+  const VERSION: u32;
+  /// Name of the store in messages, such as `image store`
+  const NAME: &'static str;
+
+  /// Version of the format the store is written in
+  fn version(&self) -> u32;
+
+  /// Open store, or returns empty one if the file does not exist.
+  ///
+  /// # Arguments
+  ///
+  /// * `path` - Path to store
+  fn open(path: &str) -> Result<Self, ApplicationError> {
+    match read(Path::new(path))? {
+      Some(contents) => Self::from_json(&contents),
+      None => Ok(Self::default()),
+    }
+  }
+
+  /// Parse store, checking the version of its format
+  fn from_json(contents: &str) -> Result<Self, ApplicationError> {
+    let store: Self = serde_json::from_str(contents)?;
+    if store.version() > Self::VERSION {
+      return Err(ApplicationError::MiscError {
+        message: format!(
+          "{} of version {} is not supported, update md2hatena",
+          Self::NAME,
+          store.version()
+        ),
+      });
+    }
+
+    Ok(store)
+  }
+
+  /// Write store, replacing the file atomically
+  fn save(&self, path: &str) -> Result<(), ApplicationError> {
+    let path = Path::new(path);
+    if let Some(dir) = path.parent() {
+      if !dir.as_os_str().is_empty() {
+        std::fs::create_dir_all(dir)?;
+      }
+    }
+
+    let mut tmp_path = path.as_os_str().to_owned();
+    tmp_path.push(".tmp");
+    std::fs::write(&tmp_path, serde_json::to_string_pretty(self)?)?;
+    std::fs::rename(&tmp_path, path)?;
+
+    Ok(())
+  }
+}
+
+/// Read contents of store, or returns `None` if the file does not exist or is empty
+pub fn read(path: &Path) -> Result<Option<String>, ApplicationError> {
+  if !path.exists() {
+    return Ok(None);
+  }
+
+  let contents = std::fs::read_to_string(path)?;
+  Ok((!contents.trim().is_empty()).then_some(contents))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use serde::Deserialize;
+
+  #[derive(Debug, PartialEq, Serialize, Deserialize)]
+  struct TestStore {
+    version: u32,
+    items: Vec<String>,
+  }
+
+  impl Default for TestStore {
+    fn default() -> Self {
+      Self {
+        version: Self::VERSION,
+        items: vec![],
+      }
+    }
+  }
+
+  impl VersionedStore for TestStore {
+    const VERSION: u32 = 2;
+    const NAME: &'static str = "test store";
+
+    fn version(&self) -> u32 {
+      self.version
+    }
+  }
+
+  #[test]
+  fn test_versioned_store() {
+    let dir = std::env::temp_dir().join("md2hatena-test-versioned-store");
+    let _ = std::fs::remove_dir_all(&dir);
+    let path = dir.join("nested/store.json").to_string_lossy().to_string();
+
+    let mut store = TestStore::open(&path).unwrap();
+    assert_eq!(store, TestStore::default());
+    store.items.push("a".into());
+    store.save(&path).unwrap();
+    assert_eq!(TestStore::open(&path).unwrap(), store);
+    assert!(!dir.join("nested/store.json.tmp").exists());
+
+    std::fs::write(&path, "  \n").unwrap();
+    assert_eq!(TestStore::open(&path).unwrap(), TestStore::default());
+
+    std::fs::write(&path, r#"{"version": 3, "items": []}"#).unwrap();
+    assert!(TestStore::open(&path)
+      .unwrap_err()
+      .to_string()
+      .contains("test store of version 3"));
+  }
+}
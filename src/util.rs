@@ -19,3 +19,20 @@ pub fn codename2extension(codename: &str) -> String {
     extension.to_string()
   }
 }
+
+/// Whether a failed request may succeed by retrying,
+/// which is true for connection failures, timeouts, server errors and 429 Too Many Requests
+pub fn is_transient_request(error: &reqwest::Error) -> bool {
+  match error.status() {
+    Some(status) => status.is_server_error() || status.as_u16() == 429,
+    None => error.is_timeout() || error.is_connect(),
+  }
+}
+
+/// Escape special characters of HTML
+pub fn escape_html(s: &str) -> String {
+  s.replace('&', "&amp;")
+    .replace('<', "&lt;")
+    .replace('>', "&gt;")
+    .replace('"', "&quot;")
+}